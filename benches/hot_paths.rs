@@ -0,0 +1,129 @@
+//! Benchmarks of the pool and scheduler hot paths. Benchmark ids are kept
+//! stable so results can be compared against a saved baseline:
+//!
+//!     cargo bench --bench hot_paths -- --save-baseline main
+//!     cargo bench --bench hot_paths -- --baseline main
+//!
+//! Criterion reports changes beyond the noise threshold as regressions and
+//! writes the estimates as JSON under `target/criterion/<group>/<id>/`.
+
+use std::{hint::black_box, sync::atomic::{AtomicU64, Ordering}, thread, time::{Duration, Instant}};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use multi_threaded_pool::{Cluster, DataManager, ObjectPool, ThreadPool};
+
+const SIZES: [u32; 3] = [100, 10_000, 100_000];
+const CLUSTER_COUNTS: [usize; 4] = [1, 2, 4, 8];
+const WRITES_PER_CLUSTER: u64 = 1_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+fn object_pool(c: &mut Criterion) {
+    let mut group = c.benchmark_group("object_pool");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("spawn", |b| b.iter_batched_ref(
+        || ObjectPool::<u64>::new(0, 1024),
+        |pool| black_box(pool.spawn()),
+        BatchSize::SmallInput,
+    ));
+    group.bench_function("destroy", |b| b.iter_batched_ref(
+        || {
+            let mut pool = ObjectPool::<u64>::new(0, 1024);
+            let spawn = pool.spawn_many(1024).swap_remove(512);
+            (pool, Some(spawn))
+        },
+        |(pool, spawn)| pool.destroy(spawn.take().unwrap()),
+        BatchSize::SmallInput,
+    ));
+
+    let mut pool = ObjectPool::<u64>::new(0, 1024);
+    let spawns = pool.spawn_many(1024);
+    let mut next = 0;
+    group.bench_function("fetch", |b| b.iter(|| {
+        next = (next + 127) % spawns.len();
+        *pool.fetch(black_box(&spawns[next])).unwrap() += 1;
+    }));
+    group.finish();
+}
+
+fn cluster_iter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cluster_iter");
+    for size in SIZES {
+        let mut cluster = Cluster::<[f32; 4], u32>::new(0, size, DataManager::new(1));
+        cluster.spawn_many_with(size as usize, |i, item| item[3] = i as f32);
+
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| cluster.iter(|pool, _| {
+                let item = pool.target();
+                item[0] += item[3] * 0.016;
+            }))
+        });
+    }
+    group.finish();
+}
+
+// every cluster writes its own cell and a cell shared by all of them
+fn data_manager_contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("data_manager_contention");
+    for clusters in CLUSTER_COUNTS {
+        let shared = DataManager::<u64>::new(clusters as u8 + 1);
+        group.throughput(Throughput::Elements(clusters as u64 * WRITES_PER_CLUSTER));
+        group.bench_with_input(BenchmarkId::from_parameter(clusters), &clusters, |b, &clusters| {
+            b.iter_custom(|iters| {
+                let begin = Instant::now();
+                thread::scope(|scope| {
+                    for thread_id in 1..=clusters {
+                        let mut shared = shared.clone();
+                        scope.spawn(move || for _ in 0..iters * WRITES_PER_CLUSTER {
+                            shared.write(thread_id, |cell| *cell += 1);
+                            shared.write(0, |cell| *cell += 1);
+                        });
+                    }
+                });
+                begin.elapsed()
+            })
+        });
+    }
+    group.finish();
+}
+
+// average time per tick of running clusters with an empty update handler
+fn update_loop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_loop_tick");
+    for clusters in CLUSTER_COUNTS.map(|n| n as u8) {
+        group.bench_with_input(BenchmarkId::from_parameter(clusters), &clusters, |b, &clusters| {
+            b.iter_custom(|iters| {
+                let mut pool = ThreadPool::<u64, u64>::new(clusters, 0);
+                let started = TICKS.load(Ordering::Acquire);
+                pool.start(|_| {}, |_, _| { TICKS.fetch_add(1, Ordering::AcqRel); });
+                while TICKS.load(Ordering::Acquire) <= started { thread::yield_now(); }
+
+                let from = TICKS.load(Ordering::Acquire);
+                let begin = Instant::now();
+                while TICKS.load(Ordering::Acquire) - from < iters * clusters as u64 { thread::yield_now(); }
+                let elapsed = begin.elapsed();
+                pool.stop();
+                elapsed / clusters as u32
+            })
+        });
+    }
+    group.finish();
+}
+
+fn config() -> Criterion {
+    Criterion::default()
+        .sample_size(50)
+        .warm_up_time(Duration::from_millis(500))
+        .measurement_time(Duration::from_secs(2))
+        .noise_threshold(0.03)
+        .significance_level(0.01)
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = object_pool, cluster_iter, data_manager_contention, update_loop
+}
+criterion_main!(benches);
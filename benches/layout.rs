@@ -0,0 +1,56 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use multi_threaded_pool::{ObjectPool, Spawn};
+
+#[derive(Default, Clone)]
+struct Particle { position: [f32; 3], velocity: [f32; 3] }
+
+// fills the pool, then destroys and respawns every third item so active
+// items end up scattered over the pool
+fn churned_pool(capacity: u32, dense: bool) -> ObjectPool<Particle> {
+    let mut pool = ObjectPool::new(0, capacity);
+    pool.set_dense(dense);
+    let spawns: Vec<Spawn> = pool.spawn_many_with(capacity as usize, |i, particle: &mut Particle| {
+        particle.velocity = [i as f32, 1.0, 0.5];
+    });
+    for spawn in spawns.iter().step_by(3) { pool.destroy(spawn.clone()); }
+    pool.spawn_many(capacity as usize / 3);
+    pool
+}
+
+fn iterate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterate_after_churn");
+    for capacity in [1_000u32, 100_000] {
+        for (layout, dense) in [("sparse", false), ("dense", true)] {
+            let mut pool = churned_pool(capacity, dense);
+            group.bench_with_input(BenchmarkId::new(layout, capacity), &capacity, |b, _| {
+                b.iter(|| pool.retain_mut(|particle, _| {
+                    for i in 0..3 { particle.position[i] += particle.velocity[i] * 0.016; }
+                    true
+                }))
+            });
+        }
+
+        let mut pool = churned_pool(capacity, true);
+        group.bench_with_input(BenchmarkId::new("dense_slice", capacity), &capacity, |b, _| {
+            b.iter(|| for particle in pool.active_items_mut().unwrap() {
+                for i in 0..3 { particle.position[i] += particle.velocity[i] * 0.016; }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn churn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("destroy_and_respawn");
+    for (layout, dense) in [("sparse", false), ("dense", true)] {
+        let mut pool = churned_pool(10_000, dense);
+        group.bench_function(layout, |b| b.iter(|| {
+            pool.destroy_where(|particle| (particle.velocity[0] as u32).is_multiple_of(4));
+            pool.spawn_many(pool.capacity() - pool.count());
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, iterate, churn);
+criterion_main!(benches);
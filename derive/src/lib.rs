@@ -0,0 +1,82 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields};
+
+/// Derives `multi_threaded_pool::SoaItem` for a struct with named fields.
+/// Generates `{Name}Columns` with one `Vec` per field, and `{Name}Slices`
+/// / `{Name}SlicesMut` with one slice per field over the active items.
+#[proc_macro_derive(SoA)]
+pub fn derive_soa(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "SoA can not be derived for generic structs"));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(&input.ident, "SoA needs a struct with named fields")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "SoA can only be derived for structs")),
+    };
+
+    let vis = &input.vis;
+    let name = &input.ident;
+    let columns = format_ident!("{}Columns", name);
+    let slices = format_ident!("{}Slices", name);
+    let slices_mut = format_ident!("{}SlicesMut", name);
+
+    let names: Vec<_> = fields.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let doc = format!("Columns of the fields of `{}`, stored by a `SoaPool`.", name);
+
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Default, Clone)]
+        #vis struct #columns {
+            #( pub #names: Vec<#types>, )*
+        }
+
+        #vis struct #slices<'a> {
+            #( pub #names: &'a [#types], )*
+        }
+
+        #vis struct #slices_mut<'a> {
+            #( pub #names: &'a mut [#types], )*
+        }
+
+        impl ::multi_threaded_pool::SoaItem for #name {
+            type Columns = #columns;
+            type Slices<'a> = #slices<'a>;
+            type SlicesMut<'a> = #slices_mut<'a>;
+
+            fn push(columns: &mut Self::Columns, item: Self) {
+                #( columns.#names.push(item.#names); )*
+            }
+
+            fn read(columns: &Self::Columns, index: usize) -> Self {
+                Self { #( #names: columns.#names[index].clone(), )* }
+            }
+
+            fn write(columns: &mut Self::Columns, index: usize, item: Self) {
+                #( columns.#names[index] = item.#names; )*
+            }
+
+            fn swap(columns: &mut Self::Columns, a: usize, b: usize) {
+                #( columns.#names.swap(a, b); )*
+            }
+
+            fn slices(columns: &Self::Columns, len: usize) -> Self::Slices<'_> {
+                #slices { #( #names: &columns.#names[..len], )* }
+            }
+
+            fn slices_mut(columns: &mut Self::Columns, len: usize) -> Self::SlicesMut<'_> {
+                #slices_mut { #( #names: &mut columns.#names[..len], )* }
+            }
+        }
+    })
+}
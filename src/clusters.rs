@@ -1,23 +1,109 @@
 use std::{
+    any::Any,
+    collections::HashSet,
     fmt::Debug, 
-    sync::{Arc, }// Mutex}
+    thread,
+    time::Duration,
+    sync::{Arc, RwLock, mpsc::Receiver}// Mutex}
 };
 
-use crate::{Spawn, pooling::ObjectPool, shared::DataManager};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::{Spawn, ThreadUpdateHandler, pooling::{ObjectPool, GENERATION_SHIFT, id_generation}, registry::PoolRegistry, ecs::World, shared::DataManager};
+use crate::scheduler::{Scheduler, System, ScheduleError};
+use crate::spatial::SpatialGrid;
+use crate::hooks::PoolHooks;
+use crate::timers::TimerWheel;
+use crate::events::{Event, EventBus, EventHandler, Subscriber};
+use crate::wake::WakeSignal;
+
+#[cfg(feature = "replay")]
+use crate::replay::{ClusterRecording, Frame};
 
 pub type ClusterIterHandler<ItemType, LocalData> = fn(&mut ObjectPool<ItemType>, &mut DataManager<LocalData>);
+pub type BuildFactory<ItemType> = (&'static str, fn(&mut ItemType));
+pub type SystemHandler<ItemType, LocalData> = fn(&mut Cluster<ItemType, LocalData>, &f32);
+pub type TimerHandler<ItemType, LocalData> = fn(&mut Cluster<ItemType, LocalData>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+pub(crate) struct ClusterTimer<ItemType, LocalData> 
+where   ItemType: Default + Clone + Send,
+        LocalData: Default + Clone + Debug,
+{
+    id: TimerId,
+    callback: TimerHandler<ItemType, LocalData>,
+    interval: Option<f32>,
+}
+pub type ClusterTask<ItemType, LocalData> = Box<dyn FnOnce(&mut Cluster<ItemType, LocalData>) + Send>;
+
+/// Pool commands sent to a running cluster from outside its thread, 
+/// see `ThreadPool::input`. These are recorded when recording is enabled.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ClusterInput {
+    Spawn,
+    Build(String),
+    Destroy(Spawn),
+}
+
+/// What happens to the items of a cluster removed from a running pool.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrainPolicy {
+    /// Items are moved to the remaining clusters, which spawn new items for 
+    /// them. Items that do not fit into any remaining cluster are dropped.
+    Migrate,
+    Drop,
+}
+
+/// What a cluster of a running `ThreadPool` does after each tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IdleStrategy {
+    /// Starts the next tick right away, keeping a core busy.
+    #[default]
+    Spin,
+    /// Lets other threads run before the next tick.
+    Yield,
+    Sleep(Duration),
+    /// Spins while tasks or events arrive, after `spins` ticks without any
+    /// the cluster parks until one arrives or `timeout` has passed, which
+    /// bounds how long the update handler and timers wait.
+    Park { spins: u32, timeout: Duration },
+    /// Only ticks when woken up, by a task or event arriving, a timer or 
+    /// item lifetime running out, `ThreadPool::wake`, or a write to a shared
+    /// data cell the cluster watches.
+    Reactive,
+}
+
+impl IdleStrategy {
+    pub(crate) fn idle(&self, idle_ticks: u32) {
+        match *self {
+            IdleStrategy::Spin => {},
+            IdleStrategy::Yield => thread::yield_now(),
+            IdleStrategy::Sleep(duration) => thread::sleep(duration),
+            IdleStrategy::Park { spins, timeout } => if idle_ticks > spins { thread::park_timeout(timeout); },
+            // waits before the tick instead, see `ThreadPool::start`
+            IdleStrategy::Reactive => {},
+        }
+    }
+}
 
 impl<LocalData: Default + Clone + Debug>  Clone for DataManager<LocalData> {
     fn clone(&self) -> Self {
-        let mut cloned_data = Vec::with_capacity(self.data.len());
-        for i in 0..self.data.len() { 
-            cloned_data.push(Arc::clone(&self.data[i]));
+        DataManager{ 
+            cells: Arc::clone(&self.cells),
+            generation: Arc::clone(&self.generation),
+            cached: RwLock::new(self.cached.read().unwrap().clone()),
+            #[cfg(feature = "replay")]
+            tape: None,
         }
-        DataManager{ data: cloned_data }
     }
 }
 
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Cluster<ItemType, LocalData> 
 where   ItemType: Default + Clone + Send,
         LocalData: Default + Clone + Debug,
@@ -25,10 +111,55 @@ where   ItemType: Default + Clone + Send,
     pub(crate) thread_id: usize, //ThreadIndex,  
 
     pub(crate) pool: ObjectPool<ItemType>,
-    pub(crate) factories: Vec<(&'static str, fn(&mut ItemType))>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) factories: Vec<BuildFactory<ItemType>>,
 
     pub shared: DataManager<LocalData>,
     //pub global: Arc<Mutex<GlobalData>>,
+
+    // additional pools for other item types, these are not part of snapshots
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub pools: PoolRegistry,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub world: World,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) systems: Vec<SystemHandler<ItemType, LocalData>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) scheduler: Scheduler<LocalData>,
+    // optional index of item positions, updated at the end of every tick
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub spatial: Option<SpatialGrid<ItemType>>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "ttl_wheel"))]
+    pub(crate) lifetimes: TimerWheel<Spawn>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "ttl_wheel"))]
+    pub(crate) timers: TimerWheel<ClusterTimer<ItemType, LocalData>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) timer_counter: u64,
+    // timers that are scheduled and not cancelled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) pending_timers: HashSet<TimerId>,
+    // connected to the other clusters of a thread pool while it is running
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) events: EventBus,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) event_queue: Option<Receiver<Event>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) subscribers: Vec<Subscriber<ItemType, LocalData>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) group: Option<&'static str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) tick_rate: Option<f32>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) idle_strategy: IdleStrategy,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) wake_signal: WakeSignal,
+    // set when the cluster is removed from a running pool, ends its thread
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) removed: bool,
+
+    #[cfg(feature = "replay")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) recorder: Option<ClusterRecording<ItemType, LocalData>>,
 }
 
 impl<ItemType, LocalData> Cluster<ItemType, LocalData> 
@@ -43,11 +174,116 @@ where   ItemType: Default + Clone + Send,
             factories: Vec::new(),
             shared: shared_data_clone,
             //global: global_data_ref,
+            pools: PoolRegistry::new(id),
+            world: World::new(id, 0),
+            systems: Vec::new(),
+            scheduler: Scheduler::default(),
+            spatial: None,
+            lifetimes: ttl_wheel(),
+            timers: ttl_wheel(),
+            timer_counter: 0,
+            pending_timers: HashSet::new(),
+            events: EventBus::default(),
+            event_queue: None,
+            subscribers: Vec::new(),
+            group: None,
+            tick_rate: None,
+            idle_strategy: IdleStrategy::default(),
+            wake_signal: WakeSignal::default(),
+            removed: false,
+            #[cfg(feature = "replay")]
+            recorder: None,
          }
     }
 
+    pub fn from_pool(mut pool: ObjectPool<ItemType>, shared_data_clone: DataManager<LocalData>) -> Self {
+        let mut lifetimes = ttl_wheel();
+        for (left, spawn) in std::mem::take(&mut pool.lifetimes) {
+            lifetimes.schedule(left, spawn);
+        }
+
+        Cluster { 
+            thread_id: *pool.thread_id(), 
+            pools: PoolRegistry::with_generation(*pool.thread_id(), id_generation(pool.spawn_id_counter)),
+            world: World::with_generation(*pool.thread_id(), 0, id_generation(pool.spawn_id_counter)),
+            systems: Vec::new(),
+            scheduler: Scheduler::default(),
+            spatial: None,
+            lifetimes,
+            timers: ttl_wheel(),
+            timer_counter: 0,
+            pending_timers: HashSet::new(),
+            events: EventBus::default(),
+            event_queue: None,
+            subscribers: Vec::new(),
+            group: None,
+            tick_rate: None,
+            idle_strategy: IdleStrategy::default(),
+            wake_signal: WakeSignal::default(),
+            removed: false,
+            pool,
+            factories: Vec::new(),
+            shared: shared_data_clone,
+            #[cfg(feature = "replay")]
+            recorder: None,
+         }
+    }
+
+    /// Takes the pool of the cluster, carrying the lifetimes of its items so
+    /// they keep running down once `from_pool` hands it to another cluster.
+    pub fn into_pool(self) -> ObjectPool<ItemType> {
+        let lifetimes = self.pending_lifetimes();
+        let mut pool = self.pool;
+        pool.lifetimes = lifetimes;
+        pool
+    }
+
+    /// Same as `into_pool`, leaving the cluster as it is.
+    pub fn pool_with_lifetimes(&self) -> ObjectPool<ItemType> {
+        let mut pool = self.pool.clone();
+        pool.lifetimes = self.pending_lifetimes();
+        pool
+    }
+
+    fn pending_lifetimes(&self) -> Vec<(f32, Spawn)> {
+        self.lifetimes.pending().map(|(left, spawn)| (left, spawn.clone())).collect()
+    }
+
+    // starts the spawn ids of an empty cluster at the generation of its thread id
+    pub(crate) fn set_generation(&mut self, generation: u32) {
+        self.pool.spawn_id_counter = (generation as u128) << GENERATION_SHIFT;
+        self.pools = PoolRegistry::with_generation(self.thread_id, generation);
+        self.world = World::with_generation(self.thread_id, 0, generation);
+    }
+
     pub fn thread_id(&self) -> &usize { &self.thread_id }
 
+    /// Name of the `ClusterGroup` the cluster was started for.
+    pub fn group(&self) -> Option<&'static str> { self.group }
+
+    pub fn tick_rate(&self) -> Option<f32> { self.tick_rate }
+
+    /// Limits how many ticks per second the cluster runs when part of a 
+    /// `ThreadPool`, None ticks as often as possible.
+    pub fn set_tick_rate(&mut self, tick_rate: Option<f32>) { self.tick_rate = tick_rate; }
+
+    pub fn idle_strategy(&self) -> IdleStrategy { self.idle_strategy }
+    pub fn set_idle_strategy(&mut self, idle_strategy: IdleStrategy) { self.idle_strategy = idle_strategy; }
+
+    /// Wakes the cluster up whenever another thread writes the shared data 
+    /// cell of the thread id, see `IdleStrategy::Reactive`.
+    pub fn watch_shared(&mut self, thread_id: usize) {
+        self.shared.watch(thread_id, self.wake_signal.clone());
+    }
+
+    /// Seconds until the next timer or item lifetime runs out.
+    pub fn until_next_timer(&self) -> Option<f32> {
+        match (self.timers.until_next_due(), self.lifetimes.until_next_due()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
     // pub fn shared_write(&mut self, thread_id: usize, access_handler: fn(&mut LocalData)) {
 
     //     let data = &mut *self.shared_data[thread_id].lock().unwrap();
@@ -88,15 +324,146 @@ where   ItemType: Default + Clone + Send,
     //     //drop(handle);
     // }
 
+    /// Replaces the cluster's world by an empty one that can hold up to
+    /// `capacity` entities, with every entity having at most one component 
+    /// of each registered type.
+    pub fn init_world(&mut self, capacity: u32) {
+        self.world = World::with_generation(self.thread_id, capacity, id_generation(self.pool.spawn_id_counter));
+    }
+
+    /// Adds a system that is run every tick, in order of registration, 
+    /// right after the cluster's update handler.
+    pub fn add_system(&mut self, system: SystemHandler<ItemType, LocalData>) {
+        self.systems.push(system);
+    }
+
+    /// Adds a named system with declared accesses to the cluster's schedule.
+    /// Returns the names of already scheduled systems it conflicts with, 
+    /// or an error if it accesses unregistered components or shared data.
+    pub fn schedule(&mut self, system: System<LocalData>) -> Result<Vec<&'static str>, ScheduleError> {
+        self.scheduler.add(system, &self.world, self.shared.len())
+    }
+
+    /// Items within radius of center, according to the spatial index as it
+    /// was at the end of the last tick or the last call to `update_spatial`.
+    pub fn query_radius(&self, center: [f32; 3], radius: f32) -> Vec<Spawn> {
+        let mut found = Vec::new();
+        if let Some(grid) = &self.spatial { grid.query_radius(&self.pool, center, radius, &mut found); }
+        found
+    }
+
+    /// Items inside the box between min and max, see `query_radius`.
+    pub fn query_aabb(&self, min: [f32; 3], max: [f32; 3]) -> Vec<Spawn> {
+        let mut found = Vec::new();
+        if let Some(grid) = &self.spatial { grid.query_aabb(&self.pool, min, max, &mut found); }
+        found
+    }
+
+    pub fn update_spatial(&mut self) {
+        if let Some(grid) = self.spatial.as_mut() { grid.update(&self.pool); }
+    }
+
+    // lets the spatial index pick up a spawned or changed item on its next update
+    fn mark_moved(&mut self, spawn: &Spawn) {
+        if let Some(grid) = self.spatial.as_mut() { grid.moved(spawn); }
+    }
+
+    // for changes to any number of items, the next update looks at all of them
+    fn mark_rescan(&mut self) {
+        if let Some(grid) = self.spatial.as_mut() { grid.rescan(); }
+    }
+
+    // everything the cluster does by itself after the update handler ran
+    pub(crate) fn end_tick(&mut self, delta_time: &f32) 
+    where   LocalData: Send + 'static,
+    {
+        self.run_systems(delta_time);
+        self.fire_timers(delta_time);
+        self.expire_items(delta_time);
+        self.update_spatial();
+    }
+
+    // one tick of a running cluster: queued tasks, events, the update handler
+    // and the end of tick work. Returns whether there was work besides the
+    // update, or None when a task removed the cluster
+    pub(crate) fn tick(
+        &mut self, 
+        tasks: impl Iterator<Item = ClusterTask<ItemType, LocalData>>,
+        opperation: ThreadUpdateHandler<ItemType, LocalData>,
+        delta_time: &f32,
+    ) -> Option<bool>
+    where   LocalData: Send + 'static,
+    {
+        #[cfg(feature = "replay")]
+        self.record_frame(delta_time);
+        let mut worked = false;
+        for task in tasks {
+            #[cfg(feature = "replay")]
+            if let Some(recorder) = self.recorder.as_mut() { recorder.replayable_task = false; }
+            task(self);
+            #[cfg(feature = "replay")]
+            if self.recorder.as_ref().is_some_and(|recorder| !recorder.replayable_task) { self.record_unrecorded(); }
+            worked = true;
+        }
+        if self.removed { return None; }
+        worked |= self.deliver_events();
+
+        (opperation)(self, delta_time);
+        self.end_tick(delta_time);
+        #[cfg(feature = "replay")]
+        self.record_result();
+        Some(worked)
+    }
+
+    // destroys items whose lifetime ended, spawns that were destroyed 
+    // earlier no longer validate and are ignored
+    pub(crate) fn expire_items(&mut self, delta_time: &f32) {
+        if self.lifetimes.is_empty() { return; }
+
+        self.lifetimes.advance(*delta_time);
+        while let Some(spawn) = self.lifetimes.pop_expired() {
+            self.destroy(spawn);
+        }
+    }
+
+    /// Number of steps the schedule takes per tick, systems within a step
+    /// run in parallel.
+    pub fn schedule_stages(&self) -> usize { self.scheduler.stage_count() }
+
+    pub(crate) fn run_systems(&mut self, delta_time: &f32) 
+    where   LocalData: Send + 'static,
+    {
+        for i in 0..self.systems.len() {
+            (self.systems[i])(self, delta_time);
+        }
+        self.scheduler.run(&mut self.world, &mut self.shared, delta_time);
+    }
+
+    pub fn set_hooks(&mut self, hooks: PoolHooks<ItemType>) {
+        self.pool.set_hooks(hooks);
+    }
+
+    /// Keeps active items packed at the front of the pool, see `ObjectPool::set_dense`.
+    pub fn set_dense(&mut self, dense: bool) {
+        self.pool.set_dense(dense);
+    }
+
+    /// The active items as one slice in iteration order, only for dense pools.
+    pub fn active_items_mut(&mut self) -> Option<&mut [ItemType]> {
+        self.mark_rescan();
+        self.pool.active_items_mut()
+    }
+
     pub fn set_build_factory(&mut self, tag: &'static str, factory_callback: fn(&mut ItemType)) {
         self.factories.push((tag, factory_callback));
     }
 
-    pub fn build(&mut self, tag: &'static str) -> Option<Spawn> {
+    pub fn build(&mut self, tag: &str) -> Option<Spawn> {
         match &self.factories.iter().position(|x| x.0 == tag) {
             Some(f_index) => {
                 if let Some(spawn) = self.pool.spawn() {
-                    (self.factories[*f_index].1)(&mut self.pool.items[spawn.pool_index]);
+                    (self.factories[*f_index].1)(self.pool.item_mut(&spawn));
+                    self.mark_moved(&spawn);
                     Some(spawn)
                 } else {
                     None
@@ -107,34 +474,263 @@ where   ItemType: Default + Clone + Send,
     }
 
     pub fn fetch(&mut self, spawn: &Spawn) -> Option<&mut ItemType> {
+        self.mark_moved(spawn);
         self.pool.fetch(spawn)
     }
 
     pub fn fetch_raw(&mut self, pool_index: usize) -> &mut ItemType {
+        self.mark_rescan();
         self.pool.fetch_raw(pool_index)
     }
 
     pub fn spawn(&mut self) -> Option<Spawn> {
-        self.pool.spawn()
+        let spawn = self.pool.spawn()?;
+        self.mark_moved(&spawn);
+        Some(spawn)
+    }
+
+    /// Spawns an item that is destroyed automatically after `ttl` seconds.
+    pub fn spawn_with_ttl(&mut self, ttl: f32) -> Option<Spawn> {
+        let spawn = self.spawn()?;
+        self.lifetimes.schedule(ttl, spawn.clone());
+        Some(spawn)
+    }
+
+    /// Builds an item that is destroyed automatically after `ttl` seconds.
+    pub fn build_with_ttl(&mut self, tag: &str, ttl: f32) -> Option<Spawn> {
+        let spawn = self.build(tag)?;
+        self.lifetimes.schedule(ttl, spawn.clone());
+        Some(spawn)
+    }
+
+    /// Calls the callback once, `delay` seconds from now.
+    pub fn after(&mut self, delay: f32, callback: TimerHandler<ItemType, LocalData>) -> TimerId {
+        self.add_timer(delay, callback, None)
+    }
+
+    /// Calls the callback every `interval` seconds until the timer is cancelled.
+    pub fn every(&mut self, interval: f32, callback: TimerHandler<ItemType, LocalData>) -> TimerId {
+        self.add_timer(interval, callback, Some(interval))
+    }
+
+    pub fn cancel_timer(&mut self, id: TimerId) {
+        self.pending_timers.remove(&id);
+    }
+
+    fn add_timer(&mut self, delay: f32, callback: TimerHandler<ItemType, LocalData>, interval: Option<f32>) -> TimerId {
+        let id = TimerId(self.timer_counter);
+        self.timer_counter += 1;
+        self.pending_timers.insert(id);
+        self.timers.schedule(delay, ClusterTimer { id, callback, interval });
+        id
+    }
+
+    pub(crate) fn fire_timers(&mut self, delta_time: &f32) {
+        if self.timers.is_empty() { return; }
+
+        self.timers.advance(*delta_time);
+        let mut fired = Vec::new();
+        while let Some(timer) = self.timers.pop_expired_with_due() {
+            fired.push(timer);
+        }
+        // fire in order of creation, callbacks may add or cancel timers
+        fired.sort_by_key(|(_, timer)| timer.id.0);
+
+        // cancelled timers are dropped once they come up
+        for (due_tick, timer) in fired {
+            if !self.pending_timers.contains(&timer.id) { continue; }
+            if timer.interval.is_none() { self.pending_timers.remove(&timer.id); }
+            (timer.callback)(self);
+
+            if let Some(interval) = timer.interval {
+                if self.pending_timers.contains(&timer.id) {
+                    self.timers.reschedule(due_tick, interval, timer);
+                }
+            }
+        }
+    }
+
+    /// Calls the handler for every event of type E published while the pool
+    /// runs, subscribe in the setup handler to not miss any events.
+    pub fn subscribe<E: Any + Send + Sync>(&mut self, handler: EventHandler<ItemType, LocalData, E>) 
+    where   ItemType: 'static,
+            LocalData: 'static,
+    {
+        self.subscribers.push(Subscriber::new(handler));
+    }
+
+    /// Sends the event to every cluster of the pool, this one included.
+    /// Returns false if the cluster is not part of a running pool.
+    pub fn publish<E: Any + Send + Sync>(&self, event: E) -> bool {
+        self.events.publish(event)
+    }
+
+    // called at the tick boundary after queued tasks ran, events published
+    // while delivering are delivered on the next tick. Returns whether there
+    // were any events
+    pub(crate) fn deliver_events(&mut self) -> bool {
+        let Some(queue) = self.event_queue.take() else { return false; };
+        let pending: Vec<Event> = queue.try_iter().collect();
+        let mut subscribers = std::mem::take(&mut self.subscribers);
+
+        for event in pending.iter() {
+            let event_type = (**event).type_id();
+            for subscriber in subscribers.iter().filter(|s| s.event_type == event_type) {
+                #[cfg(feature = "replay")]
+                self.record_unrecorded();
+                (subscriber.handler)(self, event);
+            }
+        }
+        // keep subscriptions made by the handlers
+        subscribers.append(&mut self.subscribers);
+        self.subscribers = subscribers;
+        self.event_queue = Some(queue);
+        !pending.is_empty()
     }
 
     pub fn destroy(&mut self, spawn: Spawn) {
+        if let Some(grid) = self.spatial.as_mut() { grid.remove(&spawn); }
         self.pool.destroy(spawn)
     }
 
-    pub fn iter(&mut self, handler: ClusterIterHandler<ItemType, LocalData>) {
-        self.pool.iter_position = 0;
+    pub fn spawn_many(&mut self, count: usize) -> Vec<Spawn> {
+        let spawns = self.pool.spawn_many(count);
+        spawns.iter().for_each(|spawn| self.mark_moved(spawn));
+        spawns
+    }
+
+    pub fn spawn_many_with(&mut self, count: usize, init: impl FnMut(usize, &mut ItemType)) -> Vec<Spawn> {
+        let spawns = self.pool.spawn_many_with(count, init);
+        spawns.iter().for_each(|spawn| self.mark_moved(spawn));
+        spawns
+    }
 
-        while &self.pool.iter_position < &self.pool.active_pool_count {
-            handler(&mut self.pool, &mut self.shared);
-            self.pool.iter_position += 1;
+    pub fn destroy_many(&mut self, spawns: &[Spawn]) -> usize {
+        if let Some(grid) = self.spatial.as_mut() { spawns.iter().for_each(|spawn| grid.remove(spawn)); }
+        self.pool.destroy_many(spawns)
+    }
+
+    pub fn destroy_where(&mut self, mut predicate: impl FnMut(&ItemType) -> bool) -> usize {
+        self.retain_mut(|item, _| !predicate(item))
+    }
+
+    /// Updates every item and destroys those the handler returns false for,
+    /// walking the active items once. See `ObjectPool::retain_mut`.
+    pub fn retain_mut(&mut self, mut handler: impl FnMut(&mut ItemType, &Spawn) -> bool) -> usize {
+        self.mark_rescan();
+        let grid = &mut self.spatial;
+
+        self.pool.retain_mut(|item, spawn| {
+            let keep = handler(item, spawn);
+            if let (false, Some(grid)) = (keep, grid.as_mut()) { grid.remove(spawn); }
+            keep
+        })
+    }
+
+    /// Destroys all items, together with their lifetimes and spatial index entries.
+    pub fn clear(&mut self) {
+        if let Some(grid) = self.spatial.as_mut() { grid.clear(); }
+        self.lifetimes.clear();
+        self.pool.clear();
+    }
+
+    pub fn apply(&mut self, input: ClusterInput) {
+        self.mark_replayable_task();
+        #[cfg(feature = "replay")]
+        if let Some(frame) = self.recorder.as_mut().and_then(|r| r.frames.last_mut()) {
+            frame.inputs.push(input.clone());
+        }
+        match input {
+            ClusterInput::Spawn => { self.spawn(); },
+            ClusterInput::Build(tag) => { self.build(&tag); },
+            ClusterInput::Destroy(spawn) => self.destroy(spawn),
         }
     }
 
+    pub fn iter(&mut self, handler: ClusterIterHandler<ItemType, LocalData>) {
+        self.mark_rescan();
+        let shared = &mut self.shared;
+        self.pool.for_each_active(|pool| handler(pool, shared));
+    }
+
+    /// Iterates over active items in order of the given key. The active items
+    /// are sorted in place, so plain `iter` calls keep this order afterwards.
+    pub fn iter_sorted_by_key<K: Ord>(&mut self, key: fn(&ItemType) -> K, handler: ClusterIterHandler<ItemType, LocalData>) {
+        self.pool.sort_by_key(key);
+        self.iter(handler);
+    }
+
+    /// Iterates over active items in the order they were spawned in.
+    pub fn iter_spawn_order(&mut self, handler: ClusterIterHandler<ItemType, LocalData>) {
+        self.pool.sort_by_spawn_order();
+        self.iter(handler);
+    }
+
+    /// Iterates over the active items for which the predicate returns true.
+    pub fn iter_where(&mut self, predicate: fn(&ItemType) -> bool, handler: ClusterIterHandler<ItemType, LocalData>) {
+        self.mark_rescan();
+        let shared = &mut self.shared;
+        self.pool.for_each_active(|pool| {
+            if predicate(pool.target()) { handler(pool, shared); }
+        });
+    }
+
+    // lets the running task through while recording, for tasks that only
+    // apply inputs or read
+    pub(crate) fn mark_replayable_task(&mut self) {
+        #[cfg(feature = "replay")]
+        if let Some(recorder) = self.recorder.as_mut() { recorder.replayable_task = true; }
+    }
+
     pub fn capacity(&self) -> usize { self.pool.items.len() }
     pub fn count(&self) -> usize { self.pool.active_pool_count }
+
+    #[cfg(feature = "replay")]
+    pub(crate) fn record_setup(&mut self) {
+        if let (Some(recorder), Some(tape)) = (self.recorder.as_mut(), &self.shared.tape) {
+            recorder.setup_reads = tape.lock().unwrap().take();
+        }
+    }
+
+    #[cfg(feature = "replay")]
+    pub(crate) fn record_unrecorded(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            let frame = recorder.frames.len().saturating_sub(1);
+            recorder.unrecorded.get_or_insert(frame);
+        }
+    }
+
+    #[cfg(feature = "replay")]
+    pub(crate) fn record_frame(&mut self, delta_time: &f32) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.frames.push(Frame {
+                delta_time: *delta_time,
+                inputs: Vec::new(),
+                reads: Vec::new(),
+                count: 0,
+                spawn_id_counter: 0,
+            });
+        }
+    }
+
+    #[cfg(feature = "replay")]
+    pub(crate) fn record_result(&mut self) {
+        if let Some(frame) = self.recorder.as_mut().and_then(|r| r.frames.last_mut()) {
+            frame.count = self.pool.active_pool_count;
+            frame.spawn_id_counter = self.pool.spawn_id_counter;
+            if let Some(tape) = &self.shared.tape {
+                frame.reads = tape.lock().unwrap().take();
+            }
+        }
+    }
 }
 
+// steps and size of the wheels used for item lifetimes and cluster timers
+const TTL_RESOLUTION: f32 = 0.01;
+const TTL_SLOTS: usize = 512;
+
+fn ttl_wheel<T>() -> TimerWheel<T> { TimerWheel::new(TTL_RESOLUTION, TTL_SLOTS) }
+
 
 // ObjectPool containing all clusters
 
@@ -189,4 +785,4 @@ where   ItemType: Default + Clone + Send,
 //         }
 //         ClusterPool(clusters)
 //     } 
-// }
\ No newline at end of file
+// }
@@ -0,0 +1,231 @@
+use std::{any::{Any, TypeId}, collections::HashMap};
+
+use crate::{ObjectPool, Spawn, pooling::{GENERATION_SHIFT, POOL_TAG_SHIFT, WORLD_POOL_TAG}};
+
+/// Entities are spawns from the world's entity pool, they carry no data
+/// themselves and only tie components of different types together.
+pub type Entity = Spawn;
+
+pub(crate) trait ComponentStore: Send + Sync {
+    fn remove_entity(&mut self, entity: &Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Dense pool of components of one type, with lookups in both directions
+/// between entities and the spawns of their components.
+pub struct Components<T>
+where   T: Default + Clone + Send + Sync,
+{
+    pub(crate) pool: ObjectPool<T>,
+    // indexed by entity self_index
+    by_entity: Vec<Option<(Entity, Spawn)>>,
+    // indexed by component pool_index
+    owners: Vec<Option<Entity>>,
+}
+
+impl<T> Components<T>
+where   T: Default + Clone + Send + Sync + 'static,
+{
+    fn new(thread_id: usize, capacity: u32, first_id: u128) -> Self {
+        let mut pool = ObjectPool::new(thread_id, capacity);
+        pool.spawn_id_counter = first_id;
+
+        Components {
+            pool,
+            by_entity: vec![None; capacity as usize],
+            owners: vec![None; capacity as usize],
+        }
+    }
+
+    fn spawn_of(&self, entity: &Entity) -> Option<&Spawn> {
+        match self.by_entity.get(entity.self_index) {
+            Some(Some((owner, spawn))) if owner == entity => Some(spawn),
+            _ => None,
+        }
+    }
+
+    pub fn count(&self) -> usize { self.pool.count() }
+
+    /// Calls the handler for every component together with its entity.
+    pub fn for_each(&mut self, mut handler: impl FnMut(&Entity, &mut T)) {
+        let Components { pool, owners, .. } = self;
+
+        pool.for_each_active(|pool| {
+            let pool_index = pool.target_spawn().pool_index;
+            if let Some(entity) = &owners[pool_index] {
+                handler(entity, pool.target());
+            }
+        });
+    }
+
+    // walks the dense pool of self, calling the handler for every entity 
+    // that also has a component in other
+    pub(crate) fn join<B>(&mut self, other: &Components<B>, mut handler: impl FnMut(&Entity, &mut T, &B))
+    where   B: Default + Clone + Send + Sync + 'static,
+    {
+        let Components { pool, owners, .. } = self;
+
+        pool.for_each_active(|pool| {
+            let pool_index = pool.target_spawn().pool_index;
+            let Some(entity) = &owners[pool_index] else { return; };
+            let Some(spawn_b) = other.spawn_of(entity) else { return; };
+
+            handler(entity, pool.target(), other.pool.item(spawn_b));
+        });
+    }
+}
+
+impl<T> ComponentStore for Components<T>
+where   T: Default + Clone + Send + Sync + 'static,
+{
+    fn remove_entity(&mut self, entity: &Entity) {
+        if let Some(spawn) = self.spawn_of(entity).cloned() {
+            self.by_entity[entity.self_index] = None;
+            self.owners[spawn.pool_index] = None;
+            self.pool.destroy(spawn);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+/// Minimal entity component storage for a single cluster. Every registered
+/// component type lives in its own `ObjectPool`, and queries join pools on
+/// the entities their components belong to.
+pub struct World {
+    thread_id: usize,
+    capacity: u32,
+    // generation of the cluster, without the pool tag
+    id_base: u128,
+    // component types registered so far, which tags the next component pool
+    registered: u32,
+    entities: ObjectPool<()>,
+    pub(crate) components: HashMap<TypeId, Box<dyn ComponentStore>>,
+}
+
+impl Default for World {
+    fn default() -> Self { World::new(0, 0) }
+}
+
+impl World {
+    pub fn new(thread_id: usize, capacity: u32) -> Self {
+        World::with_generation(thread_id, capacity, 0)
+    }
+
+    // tags the spawn ids of the entity and component pools with the generation
+    // of the cluster, so they never validate in any other pool of it
+    pub(crate) fn with_generation(thread_id: usize, capacity: u32, generation: u32) -> Self {
+        let id_base = (generation as u128) << GENERATION_SHIFT;
+        let mut entities = ObjectPool::new(thread_id, capacity);
+        entities.spawn_id_counter = id_base | ((WORLD_POOL_TAG as u128) << POOL_TAG_SHIFT);
+
+        World {
+            thread_id,
+            capacity,
+            id_base,
+            registered: 0,
+            entities,
+            components: HashMap::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize { self.entities.capacity() }
+    pub fn count(&self) -> usize { self.entities.count() }
+
+    pub fn register<T>(&mut self)
+    where   T: Default + Clone + Send + Sync + 'static,
+    {
+        if self.components.contains_key(&TypeId::of::<T>()) { return; }
+
+        self.registered += 1;
+        let first_id = self.id_base | (((WORLD_POOL_TAG - self.registered) as u128) << POOL_TAG_SHIFT);
+        self.components.insert(TypeId::of::<T>(), Box::new(Components::<T>::new(self.thread_id, self.capacity, first_id)));
+    }
+
+    pub fn components<T>(&mut self) -> Option<&mut Components<T>>
+    where   T: Default + Clone + Send + Sync + 'static,
+    {
+        self.components.get_mut(&TypeId::of::<T>())
+            .and_then(|store| store.as_any_mut().downcast_mut::<Components<T>>())
+    }
+
+    pub fn spawn(&mut self) -> Option<Entity> {
+        self.entities.spawn()
+    }
+
+    pub fn is_alive(&mut self, entity: &Entity) -> bool {
+        self.entities.fetch(entity).is_some()
+    }
+
+    /// Destroys an entity together with all of its components.
+    pub fn despawn(&mut self, entity: Entity) {
+        if !self.is_alive(&entity) { return; }
+
+        for store in self.components.values_mut() {
+            store.remove_entity(&entity);
+        }
+        self.entities.destroy(entity);
+    }
+
+    /// Adds a component to an entity, or replaces the one it already has.
+    /// Returns false if the entity or the component type is unknown.
+    pub fn insert<T>(&mut self, entity: &Entity, component: T) -> bool
+    where   T: Default + Clone + Send + Sync + 'static,
+    {
+        if !self.is_alive(entity) { return false; }
+        let Some(store) = self.components::<T>() else { return false; };
+
+        let spawn = match store.spawn_of(entity).cloned() {
+            Some(spawn) => spawn,
+            None => {
+                let spawn = store.pool.spawn().expect("component pools hold one item per entity");
+                store.by_entity[entity.self_index] = Some((entity.clone(), spawn.clone()));
+                store.owners[spawn.pool_index] = Some(entity.clone());
+                spawn
+            },
+        };
+        *store.pool.item_mut(&spawn) = component;
+        true
+    }
+
+    pub fn remove<T>(&mut self, entity: &Entity)
+    where   T: Default + Clone + Send + Sync + 'static,
+    {
+        if !self.entities.is_valid(entity) { return; }
+        if let Some(store) = self.components::<T>() { store.remove_entity(entity); }
+    }
+
+    pub fn get<T>(&mut self, entity: &Entity) -> Option<&mut T>
+    where   T: Default + Clone + Send + Sync + 'static,
+    {
+        if !self.entities.is_valid(entity) { return None; }
+        let store = self.components::<T>()?;
+        let spawn = store.spawn_of(entity)?.clone();
+        store.pool.fetch(&spawn)
+    }
+
+    /// Calls the handler for every entity that has a component of type A.
+    pub fn query_one<A>(&mut self, handler: impl FnMut(&Entity, &mut A))
+    where   A: Default + Clone + Send + Sync + 'static,
+    {
+        if let Some(store) = self.components::<A>() { store.for_each(handler); }
+    }
+
+    /// Calls the handler for every entity that has components of both type A
+    /// and type B, walking the dense pool of A.
+    pub fn query<A, B>(&mut self, handler: impl FnMut(&Entity, &mut A, &B))
+    where   A: Default + Clone + Send + Sync + 'static,
+            B: Default + Clone + Send + Sync + 'static,
+    {
+        let (type_a, type_b) = (TypeId::of::<A>(), TypeId::of::<B>());
+        if type_a == type_b { return; }
+
+        let [Some(store_a), Some(store_b)] = self.components.get_disjoint_mut([&type_a, &type_b]) else { return; };
+        let Some(store_a) = store_a.as_any_mut().downcast_mut::<Components<A>>() else { return; };
+        let Some(store_b) = store_b.as_any().downcast_ref::<Components<B>>() else { return; };
+
+        store_a.join(store_b, handler);
+    }
+}
@@ -0,0 +1,67 @@
+use std::{any::{Any, TypeId}, fmt::Debug, sync::{Arc, RwLock, mpsc::{self, Receiver, Sender}}};
+
+use crate::{Cluster, wake::WakeSignal};
+
+pub type Event = Arc<dyn Any + Send + Sync>;
+pub type EventHandler<ItemType, LocalData, E> = fn(&mut Cluster<ItemType, LocalData>, &E);
+
+type EventQueue = (Sender<Event>, WakeSignal);
+type SubscriberHandler<ItemType, LocalData> = dyn Fn(&mut Cluster<ItemType, LocalData>, &Event) + Send;
+
+/// Sending side of the event queues of all running clusters. Publishing an
+/// event only touches the queues, never the shared data cells, and every
+/// cluster receives events in the order they arrived at its queue.
+/// Clones share the queues, so clusters added later receive events as well.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    // indexed by thread id, with the signal waking the cluster once an event was sent
+    queues: Arc<RwLock<Vec<EventQueue>>>,
+}
+
+impl EventBus {
+    // creates the event queue of a cluster, replacing any previous one
+    pub(crate) fn connect(&self, thread_id: usize, wake_signal: WakeSignal) -> Receiver<Event> {
+        let mut queues = self.queues.write().unwrap();
+        let (sender, queue) = mpsc::channel();
+
+        // slots of clusters that are not running hold disconnected senders
+        while queues.len() <= thread_id { queues.push((mpsc::channel().0, WakeSignal::default())); }
+        queues[thread_id] = (sender, wake_signal);
+        queue
+    }
+
+    /// Sends the event to every cluster, returns false if no cluster is running.
+    pub fn publish<E: Any + Send + Sync>(&self, event: E) -> bool {
+        let event: Event = Arc::new(event);
+        let delivered = self.queues.read().unwrap().iter()
+            .filter(|(queue, wake_signal)| {
+                let sent = queue.send(Arc::clone(&event)).is_ok();
+                if sent { wake_signal.wake(); }
+                sent
+            })
+            .count();
+        delivered > 0
+    }
+}
+
+pub(crate) struct Subscriber<ItemType, LocalData>
+where   ItemType: Default + Clone + Send,
+        LocalData: Default + Clone + Debug,
+{
+    pub(crate) event_type: TypeId,
+    pub(crate) handler: Box<SubscriberHandler<ItemType, LocalData>>,
+}
+
+impl<ItemType, LocalData> Subscriber<ItemType, LocalData>
+where   ItemType: Default + Clone + Send + 'static,
+        LocalData: Default + Clone + Debug + 'static,
+{
+    pub(crate) fn new<E: Any + Send + Sync>(handler: EventHandler<ItemType, LocalData, E>) -> Self {
+        Subscriber {
+            event_type: TypeId::of::<E>(),
+            handler: Box::new(move |cluster, event| {
+                if let Some(event) = (**event).downcast_ref::<E>() { handler(cluster, event); }
+            }),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use std::fmt::Debug;
+
+use crate::{ThreadSetupHandler, ThreadUpdateHandler};
+
+/// Named set of clusters of a `ThreadPool` that run their own handlers, at
+/// their own tick rate, while sharing the pool's `DataManager`.
+pub struct ClusterGroup<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send,
+        LocalData: Default + Clone + Debug,
+{
+    pub name: &'static str,
+    pub thread_ids: Vec<usize>,
+    pub setup: ThreadSetupHandler<PoolItem, LocalData>,
+    pub update: ThreadUpdateHandler<PoolItem, LocalData>,
+    // ticks per second, None ticks as often as possible
+    pub tick_rate: Option<f32>,
+}
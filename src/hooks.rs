@@ -0,0 +1,47 @@
+pub type ItemHook<ItemType> = fn(&mut ItemType);
+
+/// Callbacks an `ObjectPool` invokes on its items. On spawn a recycled item
+/// is first reset and then passed to on_spawn, on_destroy is called before
+/// the item is returned to the pool.
+#[derive(Clone, Copy)]
+pub struct PoolHooks<ItemType> {
+    pub on_spawn: Option<ItemHook<ItemType>>,
+    pub on_destroy: Option<ItemHook<ItemType>>,
+    pub reset: Option<ItemHook<ItemType>>,
+}
+
+impl<ItemType> Default for PoolHooks<ItemType> {
+    fn default() -> Self {
+        PoolHooks { on_spawn: None, on_destroy: None, reset: None }
+    }
+}
+
+impl<ItemType: Default> PoolHooks<ItemType> {
+    /// Hooks that only reset recycled items to their default value.
+    pub fn reset_to_default() -> Self {
+        PoolHooks { reset: Some(reset_to_default::<ItemType>), ..PoolHooks::default() }
+    }
+}
+
+impl<ItemType: Poolable> PoolHooks<ItemType> {
+    /// Hooks that call the `Poolable` methods of the item type.
+    pub fn poolable() -> Self {
+        PoolHooks {
+            on_spawn: Some(ItemType::on_spawn),
+            on_destroy: Some(ItemType::on_destroy),
+            reset: Some(ItemType::reset),
+        }
+    }
+}
+
+/// Lifecycle methods for pooled item types, install them on a pool with
+/// `PoolHooks::poolable`. By default items are reset to their default value.
+pub trait Poolable: Default {
+    fn on_spawn(&mut self) {}
+    fn on_destroy(&mut self) {}
+    fn reset(&mut self) { *self = Self::default(); }
+}
+
+fn reset_to_default<ItemType: Default>(item: &mut ItemType) {
+    *item = ItemType::default();
+}
@@ -1,13 +1,52 @@
-use std::{fmt::Debug, marker::PhantomData, sync::{Arc, Mutex}, thread::{self}, time::{SystemTime}};
+use std::{fmt::Debug, marker::PhantomData, sync::{Arc, RwLock, atomic::{AtomicBool, Ordering}, mpsc::{self, Sender, Receiver}}, thread::{self, JoinHandle}, time::{Duration, Instant, SystemTime}};
 
+// lets derive macros refer to this crate by name from within it
+extern crate self as multi_threaded_pool;
+
+#[cfg(test)]
 mod tests;
 mod pooling;
 mod clusters;
 mod shared;
+mod snapshot;
+mod registry;
+mod ecs;
+mod scheduler;
+mod spatial;
+mod hooks;
+mod timers;
+mod events;
+mod groups;
+mod wake;
+#[cfg(feature = "soa")]
+mod soa;
+mod simulation;
+#[cfg(feature = "replay")]
+mod replay;
 
-use shared::DataManager;
+pub use shared::{ DataManager };
+use wake::WakeSignal;
+use pooling::{Generations, id_generation};
 pub use pooling::{ Spawn, ObjectPool };
-pub use clusters::{ Cluster };
+pub use clusters::{ Cluster, ClusterTask, ClusterInput, DrainPolicy, IdleStrategy, SystemHandler, TimerHandler, TimerId };
+pub use snapshot::{ PoolSnapshot, SnapshotError };
+pub use registry::{ PoolRegistry, PoolIterHandler };
+pub use ecs::{ World, Entity, Components };
+pub use scheduler::{ System, SystemContext, Resource, ScheduleError, ScheduledSystemHandler };
+pub use spatial::{ SpatialGrid, PositionAccessor };
+pub use hooks::{ PoolHooks, Poolable, ItemHook };
+pub use timers::{ TimerWheel };
+pub use events::{ EventBus, EventHandler };
+pub use groups::{ ClusterGroup };
+pub use simulation::{ Simulation };
+#[cfg(feature = "soa")]
+pub use soa::{ SoaItem, SoaPool };
+#[cfg(feature = "soa")]
+pub use multi_threaded_pool_derive::SoA;
+#[cfg(feature = "replay")]
+pub use replay::{ Recording, ReplayError };
+#[cfg(feature = "replay")]
+use {std::sync::Mutex, replay::{ClusterRecording, Tape}};
 
 // pub struct ThreadIndex(usize);
 
@@ -29,6 +68,7 @@ pub type ThreadUpdateHandler<PoolItem, LocalData> = fn(
 );
 
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataCell<LocalData>(LocalData);
 
 pub struct ThreadPool<PoolItem, LocalData>
@@ -36,11 +76,30 @@ pub struct ThreadPool<PoolItem, LocalData>
             LocalData: Default + Clone + Debug + Send + 'static,
 {
     pub cluster_capacity: u32,
-    pub run_handle: Arc<Mutex<bool>>,
+    pub run_handle: Arc<AtomicBool>,
     pub cluster_count: u8,
     //pub clusters: ClusterPool<PoolItem, LocalData>,
     pub shared: DataManager<LocalData>,
     pub phantom_data: PhantomData<PoolItem>,
+
+    // pools handed back by stopped clusters, or restored from a snapshot,
+    // which are picked up again by the next call to start
+    pub(crate) pools: Vec<ObjectPool<PoolItem>>,
+    // indexed by thread id, removed clusters have no worker and an inbox
+    // that is disconnected
+    pub(crate) workers: Vec<Option<JoinHandle<Cluster<PoolItem, LocalData>>>>,
+    pub(crate) inboxes: Vec<Sender<ClusterTask<PoolItem, LocalData>>>,
+    pub(crate) wake_signals: Vec<WakeSignal>,
+    // indexed by thread id, goes up whenever the cluster on it is removed
+    pub(crate) generations: Generations,
+    pub(crate) events: EventBus,
+    pub(crate) groups: Vec<ClusterGroup<PoolItem, LocalData>>,
+    pub(crate) idle_strategy: IdleStrategy,
+
+    #[cfg(feature = "replay")]
+    pub(crate) recording: bool,
+    #[cfg(feature = "replay")]
+    pub(crate) recordings: Vec<ClusterRecording<PoolItem, LocalData>>,
 }
 
 impl<PoolItem, LocalData> ThreadPool<PoolItem, LocalData>
@@ -50,12 +109,73 @@ impl<PoolItem, LocalData> ThreadPool<PoolItem, LocalData>
     pub fn new(cluster_count: u8, cluster_size: u32) -> Self {
         ThreadPool { 
             cluster_capacity: cluster_size,
-            run_handle: Arc::new(Mutex::new(false)),
+            run_handle: Arc::new(AtomicBool::new(false)),
             cluster_count,
             //clusters: ClusterPool::new(cluster_count, cluster_size, &shared_data),
             shared: DataManager::new(cluster_count),
             phantom_data: PhantomData,
+            pools: Vec::new(),
+            workers: Vec::new(),
+            inboxes: Vec::new(),
+            wake_signals: Vec::new(),
+            generations: Generations::default(),
+            events: EventBus::default(),
+            groups: Vec::new(),
+            idle_strategy: IdleStrategy::default(),
+            #[cfg(feature = "replay")]
+            recording: false,
+            #[cfg(feature = "replay")]
+            recordings: Vec::new(),
+        }
+    }
+
+    /// Restores a stopped pool from a snapshot, see `PoolSnapshot::validate`
+    /// for the snapshots that are refused.
+    pub fn from_snapshot(snapshot: PoolSnapshot<PoolItem, LocalData>) -> Result<Self, SnapshotError> {
+        snapshot.validate()?;
+        let shared = DataManager::from_cells(snapshot.shared);
+        snapshot.removed.iter().for_each(|thread_id| shared.remove_cell(*thread_id));
+
+        Ok(ThreadPool { 
+            cluster_capacity: snapshot.cluster_capacity,
+            run_handle: Arc::new(AtomicBool::new(false)),
+            cluster_count: shared.len() as u8,
+            shared,
+            phantom_data: PhantomData,
+            generations: Generations(snapshot.pools.iter().map(|pool| id_generation(pool.spawn_id_counter)).collect()),
+            pools: snapshot.pools,
+            workers: Vec::new(),
+            inboxes: Vec::new(),
+            wake_signals: Vec::new(),
+            events: EventBus::default(),
+            groups: Vec::new(),
+            idle_strategy: IdleStrategy::default(),
+            #[cfg(feature = "replay")]
+            recording: false,
+            #[cfg(feature = "replay")]
+            recordings: Vec::new(),
+        })
+    }
+
+    /// Lets the clusters of the group run the group's handlers instead of
+    /// the ones passed to `start`, from the next time they are started.
+    /// Returns false if the name is taken or a cluster is already grouped.
+    pub fn add_group(&mut self, group: ClusterGroup<PoolItem, LocalData>) -> bool {
+        if self.groups.iter().any(|other| other.name == group.name 
+            || other.thread_ids.iter().any(|id| group.thread_ids.contains(id))) 
+        {
+            return false;
         }
+        self.groups.push(group);
+        true
+    }
+
+    pub fn group_of(&self, thread_id: usize) -> Option<&'static str> {
+        self.group(thread_id).map(|group| group.name)
+    }
+
+    fn group(&self, thread_id: usize) -> Option<&ClusterGroup<PoolItem, LocalData>> {
+        self.groups.iter().find(|group| group.thread_ids.contains(&thread_id))
     }
 
     pub fn start (
@@ -66,48 +186,602 @@ impl<PoolItem, LocalData> ThreadPool<PoolItem, LocalData>
         where   PoolItem: Default + Clone + Send +'static,
                 LocalData: Default + Send + 'static,
     {
-        {
-            let handle = Arc::clone(&self.run_handle);
-            let mut running = handle.lock().unwrap();
+        if self.run_handle.swap(true, Ordering::AcqRel) { return; }
+        self.join_workers();
+        self.inboxes.clear();
+        self.wake_signals.clear();
+        self.events = EventBus::default();
+        self.shared.clear_watchers();
+        let mut pools = std::mem::take(&mut self.pools).into_iter();
 
-            if *running { return; } 
-            *running = true;
+        for thread_id in 0..self.cluster_count as usize {
+            let pool = pools.next();
+            if self.shared.contains(thread_id) {
+                let group = self.group(thread_id);
+                let (setup, opperation) = group.map_or((setup, opperation), |group| (group.setup, group.update));
+                let group = group.map(|group| (group.name, group.tick_rate));
+                self.spawn_cluster(thread_id, pool, setup, opperation, group);
+            }
         }
+    }
 
-        for i in 0..self.cluster_count as usize {
-            let run_handle = Arc::clone(&self.run_handle);
-            let thread_id = i;
-            let capacity = self.cluster_capacity;
-            let data_clone = self.shared.clone();
-            
-            thread::spawn(move || {
-                let mut cluster = Cluster::new(
-                    thread_id, capacity, data_clone
-                );
-                let mut play_time = SystemTime::now();
-                let mut delta_time;
-                {
-                    //let mut s_cluster = cluster_handle.lock().unwrap();
-                    (setup)(&mut cluster);
-                }
-                {
-                    //let mut u_cluster = cluster_handle.lock().unwrap();
-                    
-                    'active: loop {
-                        delta_time = play_time.elapsed().unwrap().as_millis() as f32 * 0.001;
-                        play_time = SystemTime::now();
-                        {
-                            if !*run_handle.lock().unwrap() { break 'active; }
-                        } {
-                            (opperation)(&mut cluster, &delta_time);
+    // starts the thread of a single cluster, from its previous pool if any
+    fn spawn_cluster(
+        &mut self, 
+        thread_id: usize,
+        pool: Option<ObjectPool<PoolItem>>,
+        setup: ThreadSetupHandler<PoolItem, LocalData>, 
+        opperation: ThreadUpdateHandler<PoolItem, LocalData>,
+        group: Option<(&'static str, Option<f32>)>,
+    ) {
+        let run_handle = Arc::clone(&self.run_handle);
+        let capacity = self.cluster_capacity;
+        let data_clone = self.shared.clone();
+        let (inbox_sender, inbox) = mpsc::channel::<ClusterTask<PoolItem, LocalData>>();
+        let wake_signal = WakeSignal::default();
+        let events = self.events.clone();
+        let event_queue = self.events.connect(thread_id, wake_signal.clone());
+        let idle_strategy = self.idle_strategy;
+        let generation = self.generations.of(thread_id);
+        #[cfg(feature = "replay")]
+        let recording = self.recording;
+
+        while self.inboxes.len() <= thread_id { self.inboxes.push(mpsc::channel().0); }
+        while self.workers.len() <= thread_id { self.workers.push(None); }
+        while self.wake_signals.len() <= thread_id { self.wake_signals.push(WakeSignal::default()); }
+        self.inboxes[thread_id] = inbox_sender;
+        self.wake_signals[thread_id] = wake_signal.clone();
+        
+        let worker = thread::spawn(move || {
+            let resumed = pool.is_some();
+            let mut cluster = match pool {
+                Some(pool) => Cluster::from_pool(pool, data_clone),
+                None => Cluster::new(thread_id, capacity, data_clone),
+            };
+            if !resumed { cluster.set_generation(generation); }
+            cluster.events = events;
+            cluster.event_queue = Some(event_queue);
+            cluster.idle_strategy = idle_strategy;
+            cluster.wake_signal = wake_signal;
+            cluster.wake_signal.attach();
+            if let Some((name, tick_rate)) = group {
+                cluster.group = Some(name);
+                cluster.tick_rate = tick_rate;
+            }
+            #[cfg(feature = "replay")]
+            if recording {
+                cluster.recorder = Some(ClusterRecording {
+                    initial: resumed.then(|| cluster.pool_with_lifetimes()),
+                    setup_reads: Vec::new(),
+                    frames: Vec::new(),
+                    unrecorded: None,
+                    replayable_task: false,
+                });
+                cluster.shared.tape = Some(Mutex::new(Tape::Recording(Vec::new())));
+            }
+            let mut play_time = SystemTime::now();
+            let mut next_tick = Instant::now();
+            let mut idle_ticks = 0;
+            let mut woken_early = false;
+            let mut delta_time;
+            {
+                //let mut s_cluster = cluster_handle.lock().unwrap();
+                (setup)(&mut cluster);
+                #[cfg(feature = "replay")]
+                cluster.record_setup();
+            }
+            {
+                //let mut u_cluster = cluster_handle.lock().unwrap();
+                
+                'active: loop {
+                    // reactive clusters only tick once woken up or when a timer is due
+                    if cluster.idle_strategy == IdleStrategy::Reactive {
+                        while !std::mem::take(&mut woken_early) && !cluster.wake_signal.take() 
+                        && run_handle.load(Ordering::Acquire) {
+                            let waited = play_time.elapsed().unwrap_or_default().as_secs_f32();
+                            match cluster.until_next_timer() {
+                                Some(due) if due <= waited => break,
+                                Some(due) => thread::park_timeout(Duration::from_secs_f32(due - waited)),
+                                None => thread::park(),
+                            }
+                        }
+                    }
+                    // whole milliseconds, the rest carries over to the next tick
+                    // so ticks shorter than a millisecond still add up for lifetimes
+                    let elapsed = play_time.elapsed().unwrap_or_default().as_millis() as u64;
+                    delta_time = elapsed as f32 * 0.001;
+                    play_time += Duration::from_millis(elapsed);
+                    {
+                        if !run_handle.load(Ordering::Acquire) { break 'active; }
+                    } 
+                    let Some(worked) = cluster.tick(inbox.try_iter(), opperation, &delta_time) else { break 'active; };
+
+                    // clusters with a tick rate park until their next tick is due,
+                    // without catching up on ticks they were late for. wake-ups 
+                    // and stopping cut the wait short
+                    if let Some(tick_rate) = cluster.tick_rate.filter(|rate| *rate > 0.0) {
+                        next_tick += Duration::from_secs_f32(1.0 / tick_rate);
+                        woken_early = false;
+                        if next_tick <= Instant::now() { next_tick = Instant::now(); }
+
+                        while !woken_early && run_handle.load(Ordering::Acquire) {
+                            let now = Instant::now();
+                            if now >= next_tick { break; }
+                            thread::park_timeout(next_tick - now);
+                            woken_early = cluster.wake_signal.take();
                         }
                     }
+                    idle_ticks = if worked { 0 } else { idle_ticks + 1 };
+                    cluster.idle_strategy.idle(idle_ticks);
                 }
+            }
+            cluster
+        });
+        self.workers[thread_id] = Some(worker);
+    }
+
+    /// Signals all clusters to stop and waits for them to finish their current
+    /// tick, blocking until every cluster thread has ended. Clusters whose
+    /// thread panicked are left with an empty pool, spawns of them stay 
+    /// invalid once the pool is started again.
+    pub fn stop(&mut self) {
+        self.run_handle.store(false, Ordering::Release);
+        self.wake_signals.iter().for_each(|wake_signal| wake_signal.wake());
+        self.join_workers();
+        self.inboxes.clear();
+        self.events = EventBus::default();
+    }
+
+    pub fn is_running(&self) -> bool { self.run_handle.load(Ordering::Acquire) }
+
+    /// Wakes a cluster up that parked while idle, which lets a reactive
+    /// cluster run its next tick. Returns false if there is no such cluster.
+    pub fn wake(&self, thread_id: usize) -> bool {
+        match self.wake_signals.get(thread_id) {
+            Some(wake_signal) if self.shared.contains(thread_id) => { wake_signal.wake(); true },
+            _ => false,
+        }
+    }
+
+    /// Sets what clusters do between ticks, from the next time they are 
+    /// started. Clusters can change their own strategy in their setup handler.
+    pub fn set_idle_strategy(&mut self, idle_strategy: IdleStrategy) { self.idle_strategy = idle_strategy; }
+
+    /// Starts an additional cluster on a running pool, with its own handlers.
+    /// Thread ids of removed clusters are reused, spawns of a removed cluster
+    /// stay invalid in the new one. Returns the new cluster's thread id, or
+    /// None if the pool is not running or already holds the maximum number
+    /// of clusters.
+    pub fn add_cluster(
+        &mut self, 
+        setup: ThreadSetupHandler<PoolItem, LocalData>, 
+        opperation: ThreadUpdateHandler<PoolItem, LocalData>,
+    ) -> Option<usize> {
+        if !self.is_running() { return None; }
+        #[cfg(feature = "replay")]
+        if self.recording { return None; }
+
+        let thread_id = self.shared.add_cell();
+        if thread_id >= u8::MAX as usize {
+            self.shared.remove_cell(thread_id);
+            return None;
+        }
+        self.cluster_count = self.shared.len() as u8;
+        self.spawn_cluster(thread_id, None, setup, opperation, None);
+        Some(thread_id)
+    }
+
+    /// Stops a cluster of a running pool at its next tick boundary and 
+    /// removes it together with its shared data cell, which other clusters
+    /// then read as the default value. Spawns of the cluster are no longer
+    /// routed anywhere. Blocks until the cluster finished its tick. Returns the
+    /// number of items migrated to other clusters, which is 0 if its thread
+    /// panicked, or None if the pool is not running or there is no such cluster.
+    pub fn remove_cluster(&mut self, thread_id: usize, drain_policy: DrainPolicy) -> Option<usize> {
+        if !self.is_running() { return None; }
+        #[cfg(feature = "replay")]
+        if self.recording { return None; }
+
+        if self.workers.get(thread_id)?.is_none() { return None; }
+        self.with_cluster(thread_id, |cluster| cluster.removed = true);
+        let worker = self.workers[thread_id].take()?;
+        let cluster = join_cluster(thread_id, worker);
+
+        self.inboxes[thread_id] = mpsc::channel().0;
+        self.generations.bump(thread_id);
+        self.shared.remove_cell(thread_id);
+        self.cluster_count = self.shared.len() as u8;
+        self.inboxes.truncate(self.cluster_count as usize);
+        self.workers.truncate(self.cluster_count as usize);
+
+        match (drain_policy, cluster) {
+            (DrainPolicy::Migrate, Some(cluster)) => Some(self.migrate(cluster.pool)),
+            _ => Some(0),
+        }
+    }
+
+    // hands the items of a removed cluster to the remaining ones, each 
+    // taking its share and passing on what did not fit
+    fn migrate(&self, mut pool: ObjectPool<PoolItem>) -> usize {
+        let mut items = Vec::with_capacity(pool.count());
+        pool.for_each_active(|pool| items.push(pool.target().clone()));
+
+        let targets: Vec<usize> = (0..self.workers.len())
+            .filter(|thread_id| self.workers[*thread_id].is_some())
+            .collect();
+        let mut migrated = 0;
+
+        for (i, thread_id) in targets.iter().enumerate() {
+            if items.is_empty() { break; }
+            let share = items.len().div_ceil(targets.len() - i);
+            let passed_on = items.split_off(share);
+            let offered = std::mem::replace(&mut items, passed_on);
+
+            let reply = self.with_cluster_reply(*thread_id, move |cluster| {
+                let (mut moved, mut left) = (0, Vec::new());
+                for item in offered {
+                    match cluster.spawn() {
+                        Some(spawn) => { *cluster.pool.item_mut(&spawn) = item; moved += 1; },
+                        None => left.push(item),
+                    }
+                }
+                (moved, left)
             });
+            if let Some(Ok((moved, left))) = reply.map(|reply| reply.recv()) {
+                migrated += moved;
+                items.extend(left);
+            }
         }
+        migrated
     }
 
-    pub fn stop(&mut self) {
-        *self.run_handle.lock().unwrap() = false;
+    /// Queues a pool command for a running cluster, which applies it at its
+    /// next tick boundary. Returns false if the cluster is not running.
+    pub fn input(&self, thread_id: usize, input: ClusterInput) -> bool {
+        self.with_cluster(thread_id, move |cluster| cluster.apply(input))
+    }
+
+    /// Queues a task that runs on the cluster's thread at its next tick 
+    /// boundary, before events are delivered. Unlike inputs, tasks are not
+    /// part of recordings, and `replay` refuses recordings during which a
+    /// task ran. Returns false if the cluster is not running.
+    pub fn with_cluster<F>(&self, thread_id: usize, task: F) -> bool
+    where   F: FnOnce(&mut Cluster<PoolItem, LocalData>) + Send + 'static,
+    {
+        let sent = match self.inboxes.get(thread_id) {
+            Some(inbox) => inbox.send(Box::new(task)).is_ok(),
+            None => false,
+        };
+        if sent { self.wake(thread_id); }
+        sent
+    }
+
+    /// Same as `with_cluster`, the value the task returns can be received 
+    /// from the returned channel once the cluster ran it.
+    pub fn with_cluster_reply<F, R>(&self, thread_id: usize, task: F) -> Option<Receiver<R>>
+    where   F: FnOnce(&mut Cluster<PoolItem, LocalData>) -> R + Send + 'static,
+            R: Send + 'static,
+    {
+        let (reply_sender, reply) = mpsc::channel();
+        self.with_cluster(thread_id, move |cluster| { let _ = reply_sender.send(task(cluster)); })
+            .then_some(reply)
+    }
+
+    /// Forwards an operation on an item to the cluster owning it, which runs
+    /// it at its next tick boundary, see `with_cluster`.
+    pub fn route<F>(&self, spawn: Spawn, operation: F) -> bool
+    where   F: FnOnce(&mut Cluster<PoolItem, LocalData>, Spawn) + Send + 'static,
+    {
+        self.with_cluster(spawn.thread_id, move |cluster| operation(cluster, spawn))
+    }
+
+    /// Queues the task on every running cluster, see `with_cluster`.
+    /// Returns the number of clusters it was queued on.
+    pub fn broadcast<F>(&self, task: F) -> usize
+    where   F: Fn(&mut Cluster<PoolItem, LocalData>) + Send + Sync + 'static,
+    {
+        let task = Arc::new(task);
+        (0..self.inboxes.len())
+            .filter(|thread_id| {
+                let task = Arc::clone(&task);
+                self.with_cluster(*thread_id, move |cluster| task(cluster))
+            })
+            .count()
+    }
+
+    /// Same as `broadcast`, every cluster replies with its thread id and the
+    /// value the task returned. The channel closes once all clusters replied.
+    pub fn broadcast_reply<F, R>(&self, task: F) -> Receiver<(usize, R)>
+    where   F: Fn(&mut Cluster<PoolItem, LocalData>) -> R + Send + Sync + 'static,
+            R: Send + 'static,
+    {
+        let task = Arc::new(task);
+        let (reply_sender, reply) = mpsc::channel();
+        for thread_id in 0..self.inboxes.len() {
+            let task = Arc::clone(&task);
+            let reply_sender = reply_sender.clone();
+            self.with_cluster(thread_id, move |cluster| { 
+                let _ = reply_sender.send((thread_id, task(cluster))); 
+            });
+        }
+        reply
+    }
+
+    /// Number of active items of a cluster. While the pool runs this waits 
+    /// for the cluster to reach its next tick boundary.
+    pub fn cluster_count_items(&self, thread_id: usize) -> Option<usize> {
+        self.query_pool(thread_id, |pool| pool.count())
+    }
+
+    /// Number of active items of every cluster, see `cluster_count_items`.
+    pub fn item_counts(&self) -> Vec<usize> {
+        (0..self.cluster_count as usize)
+            .map(|thread_id| self.cluster_count_items(thread_id).unwrap_or(0))
+            .collect()
+    }
+
+    /// Copy of an item of a cluster, None if the spawn no longer validates.
+    /// While the pool runs this waits for the cluster's next tick boundary.
+    pub fn read_item(&self, thread_id: usize, spawn: &Spawn) -> Option<PoolItem> {
+        let spawn = spawn.clone();
+        self.query_pool(thread_id, move |pool| pool.get(&spawn).cloned()).flatten()
+    }
+
+    // runs a read only query on the pool of a cluster, on the cluster's
+    // thread while running and on the pool it handed back otherwise
+    fn query_pool<R, F>(&self, thread_id: usize, query: F) -> Option<R>
+    where   F: FnOnce(&ObjectPool<PoolItem>) -> R + Send + 'static,
+            R: Send + 'static,
+    {
+        if thread_id >= self.cluster_count as usize { return None; }
+
+        if self.is_running() {
+            self.with_cluster_reply(thread_id, move |cluster| {
+                cluster.mark_replayable_task();
+                query(&cluster.pool)
+            })?.recv().ok()
+        } else {
+            match self.pools.get(thread_id) {
+                Some(pool) => Some(query(pool)),
+                None => Some(query(&ObjectPool::new(thread_id, 0))),
+            }
+        }
+    }
+
+    /// Sends the event to every running cluster, where subscribers receive it
+    /// at the next tick boundary after queued inputs were applied. Events are
+    /// not part of recordings, and `replay` refuses recordings during which a
+    /// cluster handled one. Returns false if the pool is not running.
+    pub fn publish<E: std::any::Any + Send + Sync>(&self, event: E) -> bool {
+        self.events.publish(event)
+    }
+
+    /// Copies the pool contents of every cluster and all shared data. 
+    /// Returns None while the pool is running, call `stop` first.
+    pub fn snapshot(&mut self) -> Option<PoolSnapshot<PoolItem, LocalData>> {
+        if self.is_running() { return None; }
+        self.join_workers();
+
+        let pools = (0..self.cluster_count as usize)
+            .map(|i| match self.pools.get(i) {
+                Some(pool) => pool.clone(),
+                None => self.generations.vacant_pool(i, self.cluster_capacity),
+            })
+            .collect();
+
+        Some(PoolSnapshot {
+            cluster_capacity: self.cluster_capacity,
+            pools,
+            shared: self.shared.unlinked_all(),
+            removed: self.removed_clusters(),
+        })
+    }
+
+    /// Copies the pool contents of every cluster and all shared data while 
+    /// the pool keeps running. Each cluster pauses at its next tick boundary
+    /// until every cluster has been copied, which stalls it for about one tick.
+    /// On a stopped pool this is the same as calling `snapshot`.
+    pub fn checkpoint(&mut self) -> Option<PoolSnapshot<PoolItem, LocalData>> {
+        if !self.is_running() { return self.snapshot(); }
+
+        // clusters copy their pool and then wait for a read lock on the gate,
+        // which is held for writing until shared data has been copied as well
+        let gate = Arc::new(RwLock::new(()));
+        let closed_gate = gate.write().unwrap();
+        let (pool_sender, pool_receiver) = mpsc::channel();
+
+        let removed = self.removed_clusters();
+        let running = self.inboxes.len() - removed.len();
+        for thread_id in 0..self.inboxes.len() {
+            if removed.contains(&thread_id) { continue; }
+            let gate = Arc::clone(&gate);
+            let pool_sender = pool_sender.clone();
+
+            self.with_cluster(thread_id, move |cluster| {
+                cluster.mark_replayable_task();
+                let _ = pool_sender.send(cluster.pool_with_lifetimes());
+                drop(gate.read().unwrap());
+            });
+        }
+        drop(pool_sender);
+
+        // a cluster that panicked before reaching its tick boundary drops the
+        // pool sender without sending, which ends this loop early
+        let mut pools: Vec<ObjectPool<PoolItem>> = pool_receiver.iter()
+            .take(running)
+            .collect();
+        let shared = self.shared.unlinked_all();
+        drop(closed_gate);
+
+        if pools.len() < running { return None; }
+        pools.extend(removed.iter().map(|thread_id| self.generations.vacant_pool(*thread_id, 0)));
+        pools.sort_by_key(|pool| *pool.thread_id());
+
+        Some(PoolSnapshot {
+            cluster_capacity: self.cluster_capacity,
+            pools,
+            shared,
+            removed,
+        })
+    }
+
+    fn removed_clusters(&self) -> Vec<usize> {
+        (0..self.cluster_count as usize)
+            .filter(|thread_id| !self.shared.contains(*thread_id))
+            .collect()
+    }
+
+    // waits for stopped cluster threads to hand back their pools, removed
+    // clusters are left with an empty pool. clusters whose thread panicked 
+    // get an empty pool of a new generation, as their items are lost
+    fn join_workers(&mut self) {
+        if self.workers.is_empty() { return; }
+
+        let mut clusters: Vec<Option<Cluster<PoolItem, LocalData>>> = self.workers.drain(..)
+            .enumerate()
+            .map(|(thread_id, worker)| worker.and_then(|worker| join_cluster(thread_id, worker)))
+            .collect();
+        clusters.resize_with(self.cluster_count as usize, || None);
+
+        #[cfg(feature = "replay")]
+        if self.recording {
+            // clusters can not be added or removed while recording, a missing
+            // one panicked and leaves nothing to replay
+            self.recording = false;
+            self.recordings = clusters.iter()
+                .map(|cluster| cluster.as_ref().and_then(|cluster| cluster.recorder.clone()))
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_default();
+        }
+        self.pools = clusters.into_iter().enumerate()
+            .map(|(thread_id, cluster)| match cluster {
+                Some(cluster) => cluster.into_pool(),
+                None if self.shared.contains(thread_id) => {
+                    self.generations.bump(thread_id);
+                    self.generations.vacant_pool(thread_id, self.cluster_capacity)
+                },
+                None => self.generations.vacant_pool(thread_id, 0),
+            })
+            .collect();
+    }
+}
+
+// waits for a cluster thread, one that panicked is reported and hands back nothing
+fn join_cluster<C>(thread_id: usize, worker: JoinHandle<C>) -> Option<C> {
+    match worker.join() {
+        Ok(cluster) => Some(cluster),
+        Err(_) => {
+            eprintln!("cluster {} panicked, its items are lost", thread_id);
+            None
+        },
+    }
+}
+
+#[cfg(feature = "replay")]
+impl<PoolItem, LocalData> ThreadPool<PoolItem, LocalData>
+    where   PoolItem: Default + Clone + Send + serde::Serialize + serde::de::DeserializeOwned + 'static, 
+            LocalData: Default + Clone + Debug + Send + serde::Serialize + serde::de::DeserializeOwned + 'static,
+{
+    /// Starts the pool like `start` does, while recording delta times, inputs
+    /// and shared data writes of every cluster until the pool is stopped.
+    pub fn start_recording(
+        &mut self, 
+        setup: ThreadSetupHandler<PoolItem, LocalData>, 
+        opperation: ThreadUpdateHandler<PoolItem, LocalData>,
+    ) {
+        if self.is_running() { return; }
+        self.join_workers();
+        self.recording = true;
+        self.start(setup, opperation);
+    }
+
+    /// Takes the recording made between `start_recording` and `stop`.
+    pub fn take_recording(&mut self) -> Option<Recording<PoolItem, LocalData>> {
+        if self.is_running() { return None; }
+        self.join_workers();
+        if self.recordings.is_empty() { return None; }
+
+        Some(Recording {
+            cluster_capacity: self.cluster_capacity,
+            clusters: std::mem::take(&mut self.recordings),
+        })
+    }
+
+    /// Runs a recording on a stopped pool, with all clusters stepping through
+    /// their recorded ticks in lockstep. Returns once every tick has been
+    /// replayed, after which the resulting state can be read with `snapshot`.
+    /// Recordings during which tasks or events reached a cluster are refused.
+    pub fn replay(
+        &mut self, 
+        recording: Recording<PoolItem, LocalData>,
+        setup: ThreadSetupHandler<PoolItem, LocalData>, 
+        opperation: ThreadUpdateHandler<PoolItem, LocalData>,
+    ) -> Result<(), ReplayError> {
+        if self.is_running() { return Err(ReplayError::PoolIsRunning); }
+        if recording.cluster_count() != self.cluster_count as usize { 
+            return Err(ReplayError::ClusterCountMismatch); 
+        }
+        let unrecorded = recording.clusters.iter().enumerate()
+            .find_map(|(thread_id, cluster)| cluster.unrecorded.map(|frame| (thread_id, frame)));
+        if let Some((thread_id, frame)) = unrecorded {
+            return Err(ReplayError::Unrecorded { thread_id, frame });
+        }
+        self.join_workers();
+        self.pools.clear();
+
+        let tick_count = recording.clusters.iter().map(|c| c.frames.len()).max().unwrap_or(0);
+        let lockstep = Arc::new(std::sync::Barrier::new(recording.cluster_count()));
+        let mut replays = Vec::with_capacity(recording.cluster_count());
+
+        for (thread_id, cluster_recording) in recording.clusters.into_iter().enumerate() {
+            let lockstep = Arc::clone(&lockstep);
+            let capacity = recording.cluster_capacity;
+            let data_clone = self.shared.clone();
+            let group = self.group(thread_id);
+            let (setup, opperation) = group.map_or((setup, opperation), |group| (group.setup, group.update));
+            let group = group.map(|group| group.name);
+
+            replays.push(thread::spawn(move || {
+                let mut cluster = match cluster_recording.initial {
+                    Some(pool) => Cluster::from_pool(pool, data_clone),
+                    None => Cluster::new(thread_id, capacity, data_clone),
+                };
+                cluster.group = group;
+                let mut diverged = None;
+                cluster.shared.tape = Some(Mutex::new(Tape::Playback(cluster_recording.setup_reads.into())));
+                (setup)(&mut cluster);
+
+                let mut frames = cluster_recording.frames.into_iter();
+                for tick in 0..tick_count {
+                    lockstep.wait();
+                    let Some(frame) = frames.next() else { continue; };
+                    if diverged.is_some() { continue; }
+
+                    cluster.shared.tape = Some(Mutex::new(Tape::Playback(frame.reads.into())));
+                    for input in frame.inputs { 
+                        cluster.apply(input); 
+                    }
+                    (opperation)(&mut cluster, &frame.delta_time);
+                    cluster.end_tick(&frame.delta_time);
+
+                    if cluster.pool.active_pool_count != frame.count 
+                    || cluster.pool.spawn_id_counter != frame.spawn_id_counter {
+                        diverged = Some(tick);
+                    }
+                }
+                (cluster.into_pool(), diverged)
+            }));
+        }
+
+        let mut result = Ok(());
+        for (thread_id, replay) in replays.into_iter().enumerate() {
+            let (pool, diverged) = replay.join().expect("cluster thread panicked");
+            self.pools.push(pool);
+
+            if let (Some(frame), Ok(())) = (diverged, &result) {
+                result = Err(ReplayError::Diverged { thread_id, frame });
+            }
+        }
+        result
     }
 }
\ No newline at end of file
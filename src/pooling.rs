@@ -1,16 +1,77 @@
 
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::hooks::PoolHooks;
+
+// spawn id of slots that do not hold a live item
+pub(crate) const NO_SPAWN: u128 = u128::MAX;
+// the top bits of spawn ids hold the generation of the thread id handing 
+// them out, which goes up whenever its cluster is removed, so spawns of a
+// removed cluster never validate in a cluster reusing the thread id
+pub(crate) const GENERATION_SHIFT: u32 = 96;
+// below the generation sits the pool a spawn came from within its cluster,
+// 0 for the cluster's own pool, so spawns only validate in their own pool
+pub(crate) const POOL_TAG_SHIFT: u32 = 64;
+// registry pools count their tags up from 1, the world of a cluster counts
+// down from the top, starting with its entity pool
+pub(crate) const WORLD_POOL_TAG: u32 = u32::MAX;
+// standalone struct-of-arrays pools, halfway between the two
+#[cfg(feature = "soa")]
+pub(crate) const SOA_POOL_TAG: u32 = 1 << 31;
+
+pub(crate) fn id_generation(id: u128) -> u32 { (id >> GENERATION_SHIFT) as u32 }
+
+// generation of every thread id, of the pools of `ThreadPool` and `Simulation`
+#[derive(Clone, Default)]
+pub(crate) struct Generations(pub(crate) Vec<u32>);
+
+impl Generations {
+    pub(crate) fn of(&self, thread_id: usize) -> u32 {
+        self.0.get(thread_id).copied().unwrap_or(0)
+    }
+
+    // once the cluster of a thread id ended, so its spawns never validate in
+    // the next cluster of that thread id
+    pub(crate) fn bump(&mut self, thread_id: usize) {
+        while self.0.len() <= thread_id { self.0.push(0); }
+        self.0[thread_id] += 1;
+    }
+
+    // pool of a thread id without a cluster, which keeps the generation of
+    // the thread id in its spawn ids
+    pub(crate) fn vacant_pool<ItemType>(&self, thread_id: usize, capacity: u32) -> ObjectPool<ItemType>
+    where   ItemType: Default + Clone + Send,
+    {
+        let mut pool = ObjectPool::new(thread_id, capacity);
+        pool.spawn_id_counter = (self.of(thread_id) as u128) << GENERATION_SHIFT;
+        pool
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Spawn {
+    pub(crate) thread_id: usize,
     pub(crate) id: u128,
     pub(crate) self_index: usize,
     pub(crate) pool_index: usize,
 }
 
+impl Spawn {
+    /// Thread id of the cluster owning the item.
+    pub fn thread_id(&self) -> &usize { &self.thread_id }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct ItemRef {
     pool_index: usize,
     spawn_index: usize,
 }
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ObjectPool<ItemType> 
 where   ItemType: Default + Clone + Send
 {
@@ -24,6 +85,19 @@ where   ItemType: Default + Clone + Send
 
     free_pool_items: Vec<ItemRef>,
     active_pool_items: Vec<ItemRef>,
+    // position in active_pool_items of every active item, by spawn slot
+    active_positions: Vec<usize>,
+    // dense pools keep active items at the front of items, in iteration order
+    #[cfg_attr(feature = "serde", serde(default))]
+    dense: bool,
+
+    // lifetimes of items as seconds left and spawn, only filled while the
+    // pool is not owned by a cluster, see `Cluster::into_pool`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) lifetimes: Vec<(f32, Spawn)>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: PoolHooks<ItemType>,
 }
 
 impl<ItemType> ObjectPool<ItemType> 
@@ -38,7 +112,7 @@ where   ItemType: Default + Clone + Send
         for i in 0..capacity { 
             items.push(ItemType::default()); 
             free_pool_items.push(ItemRef{ pool_index: (capacity - (i + 1)) as usize, spawn_index: 0 });
-            all_spawns.push(Spawn{ id:0, self_index: i as usize, pool_index: 0 });
+            all_spawns.push(Spawn{ thread_id: on_thread, id: NO_SPAWN, self_index: i as usize, pool_index: 0 });
         }
 
         ObjectPool { 
@@ -46,11 +120,56 @@ where   ItemType: Default + Clone + Send
             items, all_spawns,
             active_pool_count: 0, spawn_id_counter: 0, iter_position: 0,
             free_pool_items, active_pool_items,
+            active_positions: vec![0; capacity as usize],
+            dense: false,
+            lifetimes: Vec::new(),
+            hooks: PoolHooks::default(),
          }
     }
 
     pub fn thread_id(&self) -> &usize { &self.on_thread }
 
+    pub fn set_hooks(&mut self, hooks: PoolHooks<ItemType>) { self.hooks = hooks; }
+
+    pub fn is_dense(&self) -> bool { self.dense }
+
+    /// Switches between the sparse layout, where items stay at the index of
+    /// their spawn slot, and the dense layout, where active items are packed
+    /// at the front of the pool and move when other items are destroyed.
+    /// Spawns stay valid across the switch.
+    pub fn set_dense(&mut self, dense: bool) {
+        if self.dense != dense {
+            self.dense = dense;
+            self.relayout();
+            self.index_active();
+        }
+    }
+
+    /// The active items in iteration order, only available in dense pools.
+    pub fn active_items(&self) -> Option<&[ItemType]> {
+        self.dense.then(|| &self.items[..self.active_pool_count])
+    }
+
+    pub fn active_items_mut(&mut self) -> Option<&mut [ItemType]> {
+        self.dense.then(|| &mut self.items[..self.active_pool_count])
+    }
+
+    // index in items of the item of a spawn, spawns keep the index of their
+    // slot in pool_index, which dense pools map through active_positions
+    fn item_index(&self, spawn: &Spawn) -> usize {
+        if self.dense { self.active_positions[spawn.self_index] } else { spawn.pool_index }
+    }
+
+    // items of spawns that are known to be valid
+    pub(crate) fn item(&self, spawn: &Spawn) -> &ItemType {
+        &self.items[self.item_index(spawn)]
+    }
+
+    pub(crate) fn item_mut(&mut self, spawn: &Spawn) -> &mut ItemType {
+        let item_index = self.item_index(spawn);
+        &mut self.items[item_index]
+    }
+
     pub fn target(&mut self) -> &mut ItemType {
         &mut self.items[self.active_pool_items[self.iter_position].pool_index]
     }
@@ -59,9 +178,27 @@ where   ItemType: Default + Clone + Send
         &self.all_spawns[self.active_pool_items[self.iter_position].spawn_index]
     }
 
+    pub(crate) fn active_spawn(&self, active_index: usize) -> &Spawn {
+        &self.all_spawns[self.active_pool_items[active_index].spawn_index]
+    }
+
+    /// Whether the spawn belongs to this pool and its item was not destroyed.
+    pub fn is_valid(&self, spawn: &Spawn) -> bool {
+        spawn.thread_id == self.on_thread
+        && self.all_spawns.get(spawn.self_index).is_some_and(|s| s.id == spawn.id)
+    }
+
+    pub fn get(&self, spawn: &Spawn) -> Option<&ItemType> {
+        if self.is_valid(spawn) {
+            Some (self.item(spawn))
+        } else {
+            None
+        }
+    }
+
     pub fn fetch(&mut self, spawn: &Spawn) -> Option<&mut ItemType> {
-        if self.all_spawns[spawn.self_index].id == spawn.id {
-            Some (&mut self.items[spawn.pool_index])
+        if self.is_valid(spawn) {
+            Some (self.item_mut(spawn))
         } else {
             None
         }
@@ -74,32 +211,218 @@ where   ItemType: Default + Clone + Send
     pub fn spawn(&mut self) -> Option<Spawn> {
         match self.free_pool_items.pop() {
             Some(mut iref) => {
-                self.all_spawns[self.active_pool_count].id = self.spawn_id_counter;
-                self.all_spawns[self.active_pool_count].pool_index = iref.pool_index;
-                iref.spawn_index = self.active_pool_count;
+                // free refs hold a spawn slot, in sparse pools every item owns
+                // the slot with the same index, so recycling an item never
+                // overwrites the spawn of a live one. dense pools put the item
+                // behind the last active one
+                iref.spawn_index = iref.pool_index;
+                if self.dense { iref.pool_index = self.active_pool_items.len(); }
+                self.all_spawns[iref.spawn_index].id = self.spawn_id_counter;
+                self.all_spawns[iref.spawn_index].pool_index = iref.spawn_index;
 
                 self.spawn_id_counter += 1;
+                let item = &mut self.items[iref.pool_index];
+                if let Some(reset) = self.hooks.reset { reset(item); }
+                if let Some(on_spawn) = self.hooks.on_spawn { on_spawn(item); }
+
+                let spawn = self.all_spawns[iref.spawn_index].clone();
+                self.active_positions[iref.spawn_index] = self.active_pool_items.len();
                 self.active_pool_items.push(iref);
                 self.active_pool_count = self.active_pool_items.len();
     
-                Some(self.all_spawns[self.active_pool_count-1].clone())
+                Some(spawn)
             },
             _ => None,
         }
     }
 
+    pub fn spawn_many(&mut self, count: usize) -> Vec<Spawn> {
+        self.spawn_many_with(count, |_, _| {})
+    }
+
+    /// Spawns up to `count` items, fewer if the pool runs out of free items,
+    /// and calls init with the index within the batch for each of them.
+    pub fn spawn_many_with(&mut self, count: usize, mut init: impl FnMut(usize, &mut ItemType)) -> Vec<Spawn> {
+        let count = count.min(self.free_pool_items.len());
+        let mut spawns = Vec::with_capacity(count);
+        self.active_pool_items.reserve(count);
+
+        for i in 0..count {
+            let Some(spawn) = self.spawn() else { break; };
+            init(i, self.item_mut(&spawn));
+            spawns.push(spawn);
+        }
+        spawns
+    }
+
+    /// Destroys an item in constant time. The last active item takes the
+    /// place of the destroyed one in iteration order, and in dense pools
+    /// also in memory.
     pub fn destroy(&mut self, spawn: Spawn) {
-        if self.all_spawns[spawn.self_index].id == spawn.id {
-            match self.active_pool_items.iter().position(|x| x.pool_index == spawn.pool_index){
-                Some(active_index) => {
-                    self.free_pool_items.push(self.active_pool_items.remove(active_index));
-                    self.active_pool_count = self.active_pool_items.len();
-                },
-                _ => {},
+        if self.is_valid(&spawn) {
+            let active_index = self.active_positions[spawn.self_index];
+            self.all_spawns[spawn.self_index].id = NO_SPAWN;
+            if let Some(on_destroy) = self.hooks.on_destroy { on_destroy(self.item_mut(&spawn)); }
+
+            let iref = self.active_pool_items.swap_remove(active_index);
+            self.free_pool_items.push(ItemRef { pool_index: iref.spawn_index, spawn_index: 0 });
+            if self.dense { self.items.swap(active_index, self.active_pool_items.len()); }
+
+            if let Some(moved) = self.active_pool_items.get_mut(active_index) {
+                if self.dense { moved.pool_index = active_index; }
+                self.active_positions[moved.spawn_index] = active_index;
+            }
+            self.active_pool_count = self.active_pool_items.len();
+        }
+    }
+
+    /// Destroys the items of all valid spawns, returns how many were destroyed.
+    pub fn destroy_many(&mut self, spawns: &[Spawn]) -> usize {
+        let count = self.active_pool_count;
+        for spawn in spawns.iter() {
+            self.destroy(spawn.clone());
+        }
+        count - self.active_pool_count
+    }
+
+    /// Destroys every active item the predicate returns true for, returns
+    /// how many were destroyed.
+    pub fn destroy_where(&mut self, mut predicate: impl FnMut(&ItemType) -> bool) -> usize {
+        self.retain_mut(|item, _| !predicate(item))
+    }
+
+    /// Calls the handler once for every active item and destroys the items
+    /// it returns false for, in linear time. Spawns of destroyed items no 
+    /// longer validate. Returns how many items were destroyed.
+    pub fn retain_mut(&mut self, mut handler: impl FnMut(&mut ItemType, &Spawn) -> bool) -> usize {
+        let count = self.active_pool_count;
+        let mut active_index = 0;
+
+        // destroying moves the last active item to the current index, which
+        // is then handled next
+        while active_index < self.active_pool_items.len() {
+            let iref = &self.active_pool_items[active_index];
+            let spawn = &self.all_spawns[iref.spawn_index];
+
+            if handler(&mut self.items[iref.pool_index], spawn) {
+                active_index += 1;
+            } else {
+                self.destroy(spawn.clone());
             }
         }
+        count - self.active_pool_count
+    }
+
+    /// Destroys all active items in O(capacity), afterwards items are spawned
+    /// in the same order as from a new pool. Spawn ids keep counting up, so
+    /// spawns of cleared items do not validate again.
+    pub fn clear(&mut self) {
+        if let Some(on_destroy) = self.hooks.on_destroy {
+            for iref in self.active_pool_items.iter() { on_destroy(&mut self.items[iref.pool_index]); }
+        }
+        let capacity = self.items.len();
+        self.all_spawns.iter_mut().for_each(|spawn| spawn.id = NO_SPAWN);
+        self.active_pool_items.clear();
+        self.free_pool_items.clear();
+        self.free_pool_items.extend((0..capacity).rev().map(|pool_index| ItemRef { pool_index, spawn_index: 0 }));
+        self.active_pool_count = 0;
+        self.iter_position = 0;
     }
 
     pub fn capacity(&self) -> usize { self.items.len() }
     pub fn count(&self) -> usize { self.active_pool_count }
+
+    // whether the bookkeeping of the pool agrees with itself, for pools that
+    // come from outside like deserialized snapshots
+    pub(crate) fn is_consistent(&self) -> bool {
+        let capacity = self.items.len();
+        if self.all_spawns.len() != capacity || self.active_positions.len() != capacity
+            || self.active_pool_count != self.active_pool_items.len()
+            || self.active_pool_items.len() + self.free_pool_items.len() != capacity {
+            return false;
+        }
+        // every spawn slot is either active or free, exactly once
+        let mut seen = vec![false; capacity];
+        let mut claim = |slot: usize| slot < capacity && !std::mem::replace(&mut seen[slot], true);
+
+        let active_ok = self.active_pool_items.iter().enumerate().all(|(active_index, iref)| {
+            claim(iref.spawn_index)
+            && iref.pool_index == if self.dense { active_index } else { iref.spawn_index }
+            && self.active_positions[iref.spawn_index] == active_index
+            && self.all_spawns[iref.spawn_index].id != NO_SPAWN
+        });
+        active_ok && self.free_pool_items.iter().all(|iref| {
+            claim(iref.pool_index) && self.all_spawns[iref.pool_index].id == NO_SPAWN
+        })
+    }
+
+    /// Reorders the active items by key, items with equal keys stay in spawn order.
+    /// The new order is kept until items are spawned, destroyed or sorted again.
+    pub fn sort_by_key<K: Ord>(&mut self, key: fn(&ItemType) -> K) {
+        let ObjectPool { items, all_spawns, active_pool_items, .. } = self;
+
+        // unstable sorting does not allocate, spawn ids make every key unique
+        active_pool_items.sort_unstable_by(|a, b| {
+            key(&items[a.pool_index]).cmp(&key(&items[b.pool_index]))
+                .then(all_spawns[a.spawn_index].id.cmp(&all_spawns[b.spawn_index].id))
+        });
+        if self.dense { self.relayout(); }
+        self.index_active();
+    }
+
+    /// Reorders the active items in the order they were spawned in.
+    pub fn sort_by_spawn_order(&mut self) {
+        let ObjectPool { all_spawns, active_pool_items, .. } = self;
+        active_pool_items.sort_unstable_by_key(|iref| all_spawns[iref.spawn_index].id);
+        if self.dense { self.relayout(); }
+        self.index_active();
+    }
+
+    fn index_active(&mut self) {
+        for (active_index, iref) in self.active_pool_items.iter().enumerate() {
+            self.active_positions[iref.spawn_index] = active_index;
+        }
+    }
+
+    // moves the active items to where the layout wants them, at their
+    // position in iteration order for dense pools and at their slot otherwise.
+    // swaps the items in place, callers rebuild active_positions afterwards
+    // as it holds the active item at each position of items meanwhile
+    fn relayout(&mut self) {
+        const NO_OWNER: usize = usize::MAX;
+        let ObjectPool { items, active_pool_items, active_positions: owners, dense, .. } = self;
+        let target = |active_index: usize, iref: &ItemRef| if *dense { active_index } else { iref.spawn_index };
+
+        // only positions items come from or go to are ever looked at
+        for (active_index, iref) in active_pool_items.iter().enumerate() {
+            owners[target(active_index, iref)] = NO_OWNER;
+        }
+        for (active_index, iref) in active_pool_items.iter().enumerate() {
+            owners[iref.pool_index] = active_index;
+        }
+
+        // placed items never move again, as no later item targets their position
+        for active_index in 0..active_pool_items.len() {
+            let (from, to) = (active_pool_items[active_index].pool_index, target(active_index, &active_pool_items[active_index]));
+            if from == to { continue; }
+
+            items.swap(from, to);
+            let displaced = owners[to];
+            owners[from] = displaced;
+            if displaced != NO_OWNER { active_pool_items[displaced].pool_index = from; }
+            owners[to] = active_index;
+            active_pool_items[active_index].pool_index = to;
+        }
+    }
+
+    // moves the iter position over all active items, so handlers can use
+    // `target` and `target_spawn` for the item they are called for
+    pub(crate) fn for_each_active(&mut self, mut handler: impl FnMut(&mut Self)) {
+        self.iter_position = 0;
+
+        while self.iter_position < self.active_pool_count {
+            handler(self);
+            self.iter_position += 1;
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+use std::{any::{Any, TypeId}, collections::HashMap};
+
+use crate::{ObjectPool, Spawn, pooling::{GENERATION_SHIFT, POOL_TAG_SHIFT}};
+
+pub type PoolIterHandler<T> = fn(&mut ObjectPool<T>);
+
+/// Type keyed set of `ObjectPool`s owned by a single cluster, so items with
+/// different shapes can each be kept in their own dense array.
+#[derive(Default)]
+pub struct PoolRegistry {
+    thread_id: usize,
+    // first spawn id of every registered pool, without the pool tag
+    id_base: u128,
+    // pools registered so far, which tags the spawn ids of the next one
+    registered: u32,
+    pools: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl PoolRegistry {
+    pub fn new(thread_id: usize) -> Self {
+        PoolRegistry { thread_id, id_base: 0, registered: 0, pools: HashMap::new() }
+    }
+
+    pub(crate) fn with_generation(thread_id: usize, generation: u32) -> Self {
+        PoolRegistry { 
+            thread_id, 
+            id_base: (generation as u128) << GENERATION_SHIFT, 
+            registered: 0, 
+            pools: HashMap::new(),
+        }
+    }
+
+    /// Adds a pool for items of type T, replacing any previous pool of that type.
+    pub fn register<T>(&mut self, capacity: u32)
+    where   T: Default + Clone + Send + 'static
+    {
+        self.registered += 1;
+        let mut pool = ObjectPool::<T>::new(self.thread_id, capacity);
+        pool.spawn_id_counter = self.id_base | ((self.registered as u128) << POOL_TAG_SHIFT);
+        self.pools.insert(TypeId::of::<T>(), Box::new(pool));
+    }
+
+    pub fn is_registered<T: 'static>(&self) -> bool {
+        self.pools.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn pool<T>(&mut self) -> Option<&mut ObjectPool<T>>
+    where   T: Default + Clone + Send + 'static
+    {
+        self.pools.get_mut(&TypeId::of::<T>())
+            .and_then(|pool| pool.downcast_mut::<ObjectPool<T>>())
+    }
+
+    pub fn spawn<T>(&mut self) -> Option<Spawn>
+    where   T: Default + Clone + Send + 'static
+    {
+        self.pool::<T>()?.spawn()
+    }
+
+    pub fn fetch<T>(&mut self, spawn: &Spawn) -> Option<&mut T>
+    where   T: Default + Clone + Send + 'static
+    {
+        self.pool::<T>()?.fetch(spawn)
+    }
+
+    pub fn destroy<T>(&mut self, spawn: Spawn)
+    where   T: Default + Clone + Send + 'static
+    {
+        if let Some(pool) = self.pool::<T>() { pool.destroy(spawn); }
+    }
+
+    pub fn iter<T>(&mut self, handler: PoolIterHandler<T>)
+    where   T: Default + Clone + Send + 'static
+    {
+        if let Some(pool) = self.pool::<T>() { pool.for_each_active(handler); }
+    }
+
+    pub fn count<T>(&mut self) -> usize
+    where   T: Default + Clone + Send + 'static
+    {
+        self.pool::<T>().map_or(0, |pool| pool.count())
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+use crate::{ClusterInput, ObjectPool};
+
+const LOG_MAGIC: &[u8; 4] = b"MTPR";
+const LOG_VERSION: u8 = 1;
+
+/// Shared data cell values as seen by one cluster, in the order it accessed them.
+pub(crate) enum Tape<LocalData> {
+    Recording(Vec<LocalData>),
+    Playback(VecDeque<LocalData>),
+}
+
+impl<LocalData: Clone> Tape<LocalData> {
+    // called with the cell locked, before the cluster reads or writes it
+    pub(crate) fn access(&mut self, cell: &mut LocalData) {
+        match self {
+            Tape::Recording(reads) => reads.push(cell.clone()),
+            Tape::Playback(reads) => if let Some(read) = reads.pop_front() { *cell = read; },
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<LocalData> {
+        match self {
+            Tape::Recording(reads) => std::mem::take(reads),
+            Tape::Playback(_) => Vec::new(),
+        }
+    }
+}
+
+/// Everything that entered one cluster during a single tick.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Frame<LocalData> {
+    pub(crate) delta_time: f32,
+    pub(crate) inputs: Vec<ClusterInput>,
+    // every shared data cell value the cluster accessed, including values
+    // written by other threads
+    pub(crate) reads: Vec<LocalData>,
+
+    // pool state after the tick, used to detect a diverging replay
+    pub(crate) count: usize,
+    pub(crate) spawn_id_counter: u128,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ClusterRecording<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send,
+{
+    // set when the cluster resumed a pool instead of starting empty
+    pub(crate) initial: Option<ObjectPool<PoolItem>>,
+    pub(crate) setup_reads: Vec<LocalData>,
+    pub(crate) frames: Vec<Frame<LocalData>>,
+    // first frame in which a task or event reached the cluster, neither of
+    // which are recorded, so replays of the cluster cannot be trusted
+    pub(crate) unrecorded: Option<usize>,
+
+    // set by the running task if a replay reproduces what it did
+    #[serde(skip)]
+    pub(crate) replayable_task: bool,
+}
+
+/// Log of all non-deterministic input of every cluster in a `ThreadPool`,
+/// produced by `ThreadPool::start_recording` and consumed by `ThreadPool::replay`.
+///
+/// Replaying reproduces the recorded pool state as long as the update handler
+/// only depends on its delta time, its pool and its `DataManager`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Recording<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send,
+{
+    pub(crate) cluster_capacity: u32,
+    pub(crate) clusters: Vec<ClusterRecording<PoolItem, LocalData>>,
+}
+
+impl<PoolItem, LocalData> Recording<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send + Serialize + DeserializeOwned,
+        LocalData: Serialize + DeserializeOwned,
+{
+    pub fn cluster_count(&self) -> usize { self.clusters.len() }
+
+    pub fn frame_count(&self, thread_id: usize) -> usize {
+        self.clusters[thread_id].frames.len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(LOG_MAGIC);
+        bytes.push(LOG_VERSION);
+        bincode::serialize_into(&mut bytes, self).expect("recording can always be encoded");
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        if bytes.len() < 5 || &bytes[0..4] != LOG_MAGIC || bytes[4] != LOG_VERSION {
+            return Err(ReplayError::InvalidLog);
+        }
+        bincode::deserialize(&bytes[5..]).map_err(|_| ReplayError::InvalidLog)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayError {
+    InvalidLog,
+    PoolIsRunning,
+    ClusterCountMismatch,
+    Diverged { thread_id: usize, frame: usize },
+    /// A task or event reached the cluster during the frame while recording.
+    Unrecorded { thread_id: usize, frame: usize },
+}
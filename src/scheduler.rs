@@ -0,0 +1,293 @@
+use std::{any::{TypeId, type_name}, fmt::Debug, sync::{Arc, mpsc::{self, Sender, Receiver}}, thread};
+
+use crate::{Components, Entity, shared::DataManager, ecs::{ComponentStore, World}};
+
+pub type ScheduledSystemHandler<LocalData> = fn(&mut SystemContext<LocalData>, &f32);
+
+/// Something a scheduled system reads or writes during a tick. Only world
+/// components and shared data cells can be declared, see `System`.
+#[derive(Clone, Copy, Debug)]
+pub enum Resource {
+    Component(TypeId, &'static str),
+    Shared(usize),
+}
+
+impl PartialEq for Resource {
+    fn eq(&self, other: &Resource) -> bool {
+        match (self, other) {
+            (Resource::Component(a, _), Resource::Component(b, _)) => a == b,
+            (Resource::Shared(a), Resource::Shared(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Resource {
+    pub fn component<T: 'static>() -> Self {
+        Resource::Component(TypeId::of::<T>(), type_name::<T>())
+    }
+}
+
+/// A named system together with everything it accesses. Systems can only
+/// reach the world components and shared data cells they declared. The 
+/// items of the cluster's own pool and of its `PoolRegistry` are out of 
+/// their reach, logic on those belongs in systems added with 
+/// `Cluster::add_system`, which run before the scheduled ones.
+pub struct System<LocalData: Default + Clone + Debug> {
+    pub name: &'static str,
+    pub reads: Vec<Resource>,
+    pub writes: Vec<Resource>,
+    pub run: ScheduledSystemHandler<LocalData>,
+}
+
+impl<LocalData: Default + Clone + Debug> System<LocalData> {
+    fn conflicts_with(&self, other: &System<LocalData>) -> bool {
+        self.writes.iter().any(|r| other.reads.contains(r) || other.writes.contains(r))
+        || other.writes.iter().any(|r| self.reads.contains(r))
+    }
+
+    fn accesses(&self, resource: &Resource) -> bool {
+        self.reads.contains(resource) || self.writes.contains(resource)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScheduleError {
+    DuplicateName(&'static str),
+    UnknownComponent(&'static str),
+    UnknownSharedCell(usize),
+}
+
+/// Orders the scheduled systems of a cluster into stages. Systems in the same
+/// stage have no conflicting accesses and run in parallel, stages run one
+/// after the other in order of registration.
+pub struct Scheduler<LocalData: Default + Clone + Debug> {
+    systems: Vec<Arc<System<LocalData>>>,
+    stages: Vec<Vec<usize>>,
+    // threads running the systems of a stage besides the first one, kept
+    // across ticks and stopped once the scheduler is dropped
+    workers: Vec<SystemWorker<LocalData>>,
+    // runs every system on the cluster's thread, as in a `Simulation`
+    pub(crate) sequential: bool,
+}
+
+struct SystemWorker<LocalData: Default + Clone + Debug> {
+    jobs: Sender<(SystemContext<LocalData>, f32)>,
+    done: Receiver<SystemContext<LocalData>>,
+}
+
+impl<LocalData: Default + Clone + Debug> Default for Scheduler<LocalData> {
+    fn default() -> Self { Scheduler { systems: Vec::new(), stages: Vec::new(), workers: Vec::new(), sequential: false } }
+}
+
+impl<LocalData: Default + Clone + Debug> Scheduler<LocalData> {
+    /// Adds a system to the schedule and returns the names of the systems it
+    /// conflicts with, which will never run at the same time as it does.
+    pub(crate) fn add(&mut self, system: System<LocalData>, world: &World, shared_cells: usize)
+        -> Result<Vec<&'static str>, ScheduleError>
+    {
+        if self.systems.iter().any(|s| s.name == system.name) {
+            return Err(ScheduleError::DuplicateName(system.name));
+        }
+        for resource in system.reads.iter().chain(system.writes.iter()) {
+            match resource {
+                Resource::Component(type_id, name) => if !world.components.contains_key(type_id) {
+                    return Err(ScheduleError::UnknownComponent(name));
+                },
+                Resource::Shared(cell) => if *cell >= shared_cells {
+                    return Err(ScheduleError::UnknownSharedCell(*cell));
+                },
+            }
+        }
+
+        let conflicts: Vec<usize> = (0..self.systems.len())
+            .filter(|i| self.systems[*i].conflicts_with(&system))
+            .collect();
+
+        // place the system in the first stage after the last one holding a
+        // system it conflicts with, so conflicting systems keep their order
+        let first_free_stage = self.stages.iter()
+            .rposition(|stage| stage.iter().any(|i| conflicts.contains(i)))
+            .map_or(0, |stage| stage + 1);
+
+        let index = self.systems.len();
+        match self.stages.get_mut(first_free_stage) {
+            Some(stage) => stage.push(index),
+            None => self.stages.push(vec![index]),
+        }
+
+        let names = conflicts.iter().map(|i| self.systems[*i].name).collect();
+        self.systems.push(Arc::new(system));
+        Ok(names)
+    }
+
+    pub(crate) fn stage_count(&self) -> usize { self.stages.len() }
+
+    pub(crate) fn run(&mut self, world: &mut World, shared: &mut DataManager<LocalData>, delta_time: &f32)
+    where   LocalData: Send + 'static,
+    {
+        for stage in 0..self.stages.len() {
+            self.run_stage(stage, world, shared, delta_time);
+        }
+    }
+
+    fn run_stage(&mut self, stage: usize, world: &mut World, shared: &mut DataManager<LocalData>, delta_time: &f32)
+    where   LocalData: Send + 'static,
+    {
+        let stage = &self.stages[stage];
+        let mut contexts: Vec<SystemContext<LocalData>> = stage.iter()
+            .map(|i| SystemContext {
+                system: Arc::clone(&self.systems[*i]),
+                writes: Vec::new(),
+                reads: Vec::new(),
+                shared: shared.clone(),
+            })
+            .collect();
+
+        // take every store used in this stage out of the world, systems in a
+        // stage never write a store that another one accesses
+        for context in contexts.iter_mut() {
+            for resource in context.system.writes.iter() {
+                if let Resource::Component(type_id, _) = resource {
+                    if let Some(store) = world.components.remove(type_id) {
+                        context.writes.push((*type_id, store));
+                    }
+                }
+            }
+        }
+        let mut reads: Vec<(TypeId, Arc<Box<dyn ComponentStore>>)> = Vec::new();
+        for system in stage.iter().map(|i| &self.systems[*i]) {
+            for resource in system.reads.iter() {
+                if let Resource::Component(type_id, _) = resource {
+                    if let Some(store) = world.components.remove(type_id) {
+                        reads.push((*type_id, Arc::new(store)));
+                    }
+                }
+            }
+        }
+        for context in contexts.iter_mut() {
+            for (type_id, store) in reads.iter() {
+                if context.system.reads.contains(&Resource::Component(*type_id, "")) {
+                    context.reads.push((*type_id, Arc::clone(store)));
+                }
+            }
+        }
+
+        // systems run one after the other on the cluster's own thread in 
+        // simulations, and while shared data accesses are taped so recordings
+        // replay in order
+        #[cfg(feature = "replay")]
+        let sequential = self.sequential || shared.tape.is_some();
+        #[cfg(not(feature = "replay"))]
+        let sequential = self.sequential;
+
+        if sequential || contexts.len() == 1 {
+            for context in contexts.iter_mut() {
+                #[cfg(feature = "replay")]
+                { context.shared.tape = shared.tape.take(); }
+                (context.system.run)(context, delta_time);
+                #[cfg(feature = "replay")]
+                { shared.tape = context.shared.tape.take(); }
+            }
+        } else {
+            // the first system of a stage runs on the cluster's own thread
+            while self.workers.len() < contexts.len() - 1 { self.workers.push(SystemWorker::start()); }
+            let mut first = contexts.remove(0);
+            for (worker, context) in self.workers.iter().zip(contexts.drain(..)) {
+                worker.jobs.send((context, *delta_time)).expect("system workers run while the scheduler exists");
+            }
+            (first.system.run)(&mut first, delta_time);
+
+            contexts.push(first);
+            for worker in self.workers.iter().take(stage.len() - 1) {
+                contexts.push(worker.done.recv().expect("scheduled system panicked"));
+            }
+        }
+
+        for context in contexts.into_iter() {
+            for (type_id, store) in context.writes.into_iter() {
+                world.components.insert(type_id, store);
+            }
+        }
+        for (type_id, store) in reads.into_iter() {
+            let store = Arc::try_unwrap(store).ok().expect("contexts of a stage end with the stage");
+            world.components.insert(type_id, store);
+        }
+    }
+}
+
+impl<LocalData> SystemWorker<LocalData>
+where   LocalData: Default + Clone + Debug + Send + 'static,
+{
+    fn start() -> Self {
+        let (jobs, job_queue) = mpsc::channel::<(SystemContext<LocalData>, f32)>();
+        let (done_sender, done) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (mut context, delta_time) in job_queue {
+                (context.system.run)(&mut context, &delta_time);
+                if done_sender.send(context).is_err() { break; }
+            }
+        });
+        SystemWorker { jobs, done }
+    }
+}
+
+/// Access to the resources a scheduled system declared, for a single tick.
+pub struct SystemContext<LocalData: Default + Clone + Debug> {
+    system: Arc<System<LocalData>>,
+    writes: Vec<(TypeId, Box<dyn ComponentStore>)>,
+    reads: Vec<(TypeId, Arc<Box<dyn ComponentStore>>)>,
+    shared: DataManager<LocalData>,
+}
+
+impl<LocalData: Default + Clone + Debug> SystemContext<LocalData> {
+    pub fn name(&self) -> &'static str { self.system.name }
+
+    pub fn write<T>(&mut self) -> Option<&mut Components<T>>
+    where   T: Default + Clone + Send + Sync + 'static,
+    {
+        self.writes.iter_mut()
+            .find(|(type_id, _)| *type_id == TypeId::of::<T>())
+            .and_then(|(_, store)| store.as_any_mut().downcast_mut::<Components<T>>())
+    }
+
+    pub fn read<T>(&self) -> Option<&Components<T>>
+    where   T: Default + Clone + Send + Sync + 'static,
+    {
+        self.reads.iter()
+            .find(|(type_id, _)| *type_id == TypeId::of::<T>())
+            .and_then(|(_, store)| store.as_any().downcast_ref::<Components<T>>())
+    }
+
+    /// Calls the handler for every entity having both a component of type A,
+    /// which the system writes, and one of type B, which it reads.
+    pub fn query<A, B>(&mut self, handler: impl FnMut(&Entity, &mut A, &B))
+    where   A: Default + Clone + Send + Sync + 'static,
+            B: Default + Clone + Send + Sync + 'static,
+    {
+        let SystemContext { writes, reads, .. } = self;
+
+        let Some(store_a) = writes.iter_mut()
+            .find(|(type_id, _)| *type_id == TypeId::of::<A>())
+            .and_then(|(_, store)| store.as_any_mut().downcast_mut::<Components<A>>()) else { return; };
+        let Some(store_b) = reads.iter()
+            .find(|(type_id, _)| *type_id == TypeId::of::<B>())
+            .and_then(|(_, store)| store.as_any().downcast_ref::<Components<B>>()) else { return; };
+
+        store_a.join(store_b, handler);
+    }
+
+    /// Returns a copy of a shared data cell the system declared to read or write.
+    pub fn read_shared(&self, thread_id: usize) -> Option<LocalData> {
+        self.system.accesses(&Resource::Shared(thread_id))
+            .then(|| self.shared.unlinked(thread_id))
+    }
+
+    /// Writes a shared data cell the system declared to write, returns false otherwise.
+    pub fn write_shared(&mut self, thread_id: usize, data_handler: fn(&mut LocalData)) -> bool {
+        if !self.system.writes.contains(&Resource::Shared(thread_id)) { return false; }
+        self.shared.write(thread_id, data_handler);
+        true
+    }
+}
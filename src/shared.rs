@@ -1,78 +1,216 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, Ordering}};
 use std::fmt::Debug;
 
-use crate::DataCell;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer, Deserialize, Deserializer, ser::SerializeSeq};
 
+use crate::{DataCell, wake::WakeSignal};
+#[cfg(feature = "replay")]
+use crate::replay::Tape;
+
+
+pub(crate) type SharedCell<LocalData> = Arc<Mutex<DataCell<LocalData>>>;
+
+// the cells of all clusters together with the clusters watching them, 
+// replaced by a changed copy whenever cells are added or removed or
+// watchers change
+#[derive(Clone)]
+pub(crate) struct Cells<LocalData> {
+    // one slot per cluster thread id, removed clusters leave a None
+    data: Vec<Option<SharedCell<LocalData>>>,
+    // clusters woken up by writes to a cell, as pairs of cell and signal
+    watchers: Vec<(usize, WakeSignal)>,
+}
 
 pub struct DataManager<LocalData: Default + Clone + Debug> {
-    pub(crate) data: Vec<Arc<Mutex<DataCell<LocalData>>>>,
+    // latest cells, shared by all clones so cells can be added and removed
+    // while clusters run
+    pub(crate) cells: Arc<RwLock<Arc<Cells<LocalData>>>>,
+    // goes up with every change of the cells
+    pub(crate) generation: Arc<AtomicU64>,
+    // cells this clone loaded last, with their generation, so accesses only
+    // take the shared lock after the cells changed
+    pub(crate) cached: RwLock<(u64, Arc<Cells<LocalData>>)>,
+
+    #[cfg(feature = "replay")]
+    pub(crate) tape: Option<Mutex<Tape<LocalData>>>,
 }
 
 impl<LocalData: Default + Clone + Debug> DataManager<LocalData> {
     pub fn new(cluster_count: u8) -> Self {
-        let mut data = Vec::with_capacity(cluster_count as usize);
-        for _i in 0..cluster_count as usize { 
-            data.push(Arc::new(Mutex::new(DataCell::default())));
+        Self::from_cells(vec![LocalData::default(); cluster_count as usize])
+    }
+
+    pub(crate) fn from_cells(cells: Vec<LocalData>) -> Self {
+        let data = cells.into_iter()
+            .map(|cell| Some(Arc::new(Mutex::new(DataCell(cell)))))
+            .collect();
+        let cells = Arc::new(Cells { data, watchers: Vec::new() });
+        DataManager { 
+            cells: Arc::new(RwLock::new(Arc::clone(&cells))),
+            generation: Arc::default(),
+            cached: RwLock::new((0, cells)),
+            #[cfg(feature = "replay")]
+            tape: None,
+        }
+    }
+
+    // runs the handler on the cells as of the last change, which are only
+    // reloaded when the generation moved on
+    fn with_cells<R>(&self, handler: impl FnOnce(&Cells<LocalData>) -> R) -> R {
+        let generation = self.generation.load(Ordering::Acquire);
+        {
+            let cached = self.cached.read().unwrap();
+            if cached.0 == generation { return handler(&cached.1); }
         }
-        DataManager { data }
+        let latest = Arc::clone(&self.cells.read().unwrap());
+        let mut cached = self.cached.write().unwrap();
+        *cached = (generation, latest);
+        handler(&cached.1)
+    }
+
+    // publishes a changed copy of the cells
+    fn change<R>(&self, change: impl FnOnce(&mut Cells<LocalData>) -> R) -> R {
+        let mut cells = self.cells.write().unwrap();
+        let mut changed = Cells::clone(&cells);
+        let result = change(&mut changed);
+        *cells = Arc::new(changed);
+        self.generation.fetch_add(1, Ordering::Release);
+        result
+    }
+
+    /// Number of cell slots, which is one more than the highest thread id.
+    pub fn len(&self) -> usize { self.with_cells(|cells| cells.data.len()) }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Whether there is a cell for the thread id, cells of removed clusters are gone.
+    pub fn contains(&self, thread_id: usize) -> bool { 
+        self.with_cells(|cells| cells.data.get(thread_id).is_some_and(Option::is_some))
+    }
+
+    // adds a cell in the first free slot and returns its thread id
+    pub(crate) fn add_cell(&self) -> usize {
+        self.change(|cells| {
+            let cell = Some(Arc::new(Mutex::new(DataCell::default())));
+            match cells.data.iter().position(|slot| slot.is_none()) {
+                Some(thread_id) => { cells.data[thread_id] = cell; thread_id },
+                None => { cells.data.push(cell); cells.data.len() - 1 },
+            }
+        })
+    }
+
+    pub(crate) fn remove_cell(&self, thread_id: usize) {
+        self.change(|cells| {
+            cells.watchers.retain(|(cell, _)| *cell != thread_id);
+            if let Some(slot) = cells.data.get_mut(thread_id) { *slot = None; }
+            while let Some(None) = cells.data.last() { cells.data.pop(); }
+        });
+    }
+
+    pub(crate) fn watch(&self, thread_id: usize, wake_signal: WakeSignal) {
+        self.change(|cells| cells.watchers.push((thread_id, wake_signal)));
+    }
+
+    pub(crate) fn clear_watchers(&self) {
+        self.change(|cells| cells.watchers.clear());
+    }
+
+    // every cell access goes through here, so reads can be taped for replay.
+    // accesses to cells of removed clusters do nothing
+    fn access<R>(&self, thread_id: usize, data_handler: impl FnOnce(&mut LocalData) -> R) -> Option<R> {
+        self.with_cells(|cells| {
+            let mut handle = cells.data.get(thread_id)?.as_ref()?.lock().unwrap();
+
+            #[cfg(feature = "replay")]
+            if let Some(tape) = &self.tape {
+                tape.lock().unwrap().access(&mut handle.0);
+            }
+            Some(data_handler(&mut handle.0))
+        })
+    }
+
+    // accesses that may change a cell wake up the clusters watching it,
+    // except the cluster doing the write
+    fn modify<R>(&self, thread_id: usize, data_handler: impl FnOnce(&mut LocalData) -> R) -> Option<R> {
+        let result = self.access(thread_id, data_handler);
+
+        self.with_cells(|cells| {
+            for (_, wake_signal) in cells.watchers.iter().filter(|(cell, _)| *cell == thread_id) {
+                if !wake_signal.is_current_thread() { wake_signal.wake(); }
+            }
+        });
+        result
     }
 
     pub fn write(&mut self, thread_id: usize, data_handler: fn(&mut LocalData)) {
 
-        let handle = &mut *self.data[thread_id].lock().unwrap();
-        data_handler(&mut handle.0);
-        drop(handle);
+        self.modify(thread_id, data_handler);
     }
 
     pub fn write_all(&mut self, data_handler: fn(&mut LocalData)) {
-        let mut i =  self.data.len();
+        let mut i =  self.len();
         while i > 0 {
             i -= 1;
-            let handle = &mut *self.data[i].lock().unwrap();
-            data_handler(&mut handle.0);
-            drop(handle);
+            self.modify(i, data_handler);
         }
     }
 
     pub fn catch<T>(&mut self, thread_id: usize, value: &T, data_handler: fn(&T, &mut LocalData)) {
 
-        let handle = &mut *self.data[thread_id].lock().unwrap();
-        data_handler(value, &mut handle.0);
-        drop(handle);
+        self.modify(thread_id, |data| data_handler(value, data));
     }
 
     pub fn catch_all<T>(&mut self, value: &T, data_handler: fn(&T, &mut LocalData)) {
-        let mut i =  self.data.len();
+        let mut i =  self.len();
         while i > 0 {
             i -= 1;
-            let handle = &mut *self.data[i].lock().unwrap();
-            data_handler(value, &mut handle.0);
-            drop(handle);
+            self.modify(i, |data| data_handler(value, data));
         }
     }
 
     pub fn catch_mut<T>(&mut self, thread_id: usize, value: &mut T, data_handler: fn(&mut T, &mut LocalData)) {
 
-        let handle = &mut *self.data[thread_id].lock().unwrap();
-        data_handler(value, &mut handle.0);
-        drop(handle);
+        self.modify(thread_id, |data| data_handler(value, data));
     }
 
     pub fn catch_mut_all<T>(&mut self, value: &mut T, data_handler: fn(&mut T, &mut LocalData)) {
-        let mut i =  self.data.len();
+        let mut i =  self.len();
         while i > 0 {
             i -= 1;
-            let handle = &mut *self.data[i].lock().unwrap();
-            data_handler(value, &mut handle.0);
-            drop(handle);
+            self.modify(i, |data| data_handler(value, data));
         }
     }
 
+    /// Copy of a cell, the default value if its cluster was removed.
     pub fn unlinked(&self, thread_id: usize) -> LocalData {
 
-        let handle = self.data[thread_id].lock().unwrap();
-        let clone = handle.0.clone();
-        drop(handle);
-        clone
+        self.access(thread_id, |data| data.clone()).unwrap_or_default()
+    }
+
+    pub fn unlinked_all(&self) -> Vec<LocalData> {
+        (0..self.len()).map(|i| self.unlinked(i)).collect()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<LocalData> Serialize for DataManager<LocalData> 
+where   LocalData: Default + Clone + Debug + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let cells = self.unlinked_all();
+        let mut seq = serializer.serialize_seq(Some(cells.len()))?;
+        for cell in cells.iter() {
+            seq.serialize_element(cell)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, LocalData> Deserialize<'de> for DataManager<LocalData> 
+where   LocalData: Default + Clone + Debug + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(DataManager::from_cells(Vec::deserialize(deserializer)?))
     }
 }
\ No newline at end of file
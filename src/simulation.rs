@@ -0,0 +1,182 @@
+use std::fmt::Debug;
+
+use crate::{Cluster, ClusterTask, EventBus, IdleStrategy, PoolSnapshot, ThreadPool, ThreadSetupHandler, ThreadUpdateHandler};
+use crate::{pooling::Generations, shared::DataManager, wake::WakeSignal};
+
+struct SimulatedCluster<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send,
+        LocalData: Default + Clone + Debug,
+{
+    cluster: Cluster<PoolItem, LocalData>,
+    opperation: ThreadUpdateHandler<PoolItem, LocalData>,
+    tasks: Vec<ClusterTask<PoolItem, LocalData>>,
+    // virtual time of the last tick
+    ticked_at: f64,
+}
+
+/// Runs the clusters of a `ThreadPool` on the calling thread, in thread id
+/// order, with virtual time. Every `step` ticks each cluster at most once,
+/// so logic built on `Cluster` and `DataManager` can be tested without
+/// threads or sleeps. Scheduled systems run one after the other as well.
+pub struct Simulation<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send,
+        LocalData: Default + Clone + Debug,
+{
+    pub shared: DataManager<LocalData>,
+    cluster_capacity: u32,
+    clusters: Vec<Option<SimulatedCluster<PoolItem, LocalData>>>,
+    generations: Generations,
+    events: EventBus,
+    time: f64,
+    ticks: u64,
+}
+
+impl<PoolItem, LocalData> Simulation<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send + 'static,
+        LocalData: Default + Clone + Debug + Send + 'static,
+{
+    pub fn new(
+        cluster_count: u8,
+        cluster_size: u32,
+        setup: ThreadSetupHandler<PoolItem, LocalData>,
+        opperation: ThreadUpdateHandler<PoolItem, LocalData>,
+    ) -> Self {
+        Self::from_pool(&mut ThreadPool::new(cluster_count, cluster_size), setup, opperation)
+            .expect("new pools are not running")
+    }
+
+    /// Takes the clusters of a stopped pool, with their groups, idle strategy
+    /// and shared data, and runs their setup handlers. Returns None if the
+    /// pool is running.
+    pub fn from_pool(
+        pool: &mut ThreadPool<PoolItem, LocalData>,
+        setup: ThreadSetupHandler<PoolItem, LocalData>,
+        opperation: ThreadUpdateHandler<PoolItem, LocalData>,
+    ) -> Option<Self> {
+        if pool.is_running() { return None; }
+        pool.join_workers();
+        pool.shared.clear_watchers();
+        let events = EventBus::default();
+        let mut pools = std::mem::take(&mut pool.pools).into_iter();
+        let mut clusters = Vec::with_capacity(pool.cluster_count as usize);
+
+        for thread_id in 0..pool.cluster_count as usize {
+            let previous = pools.next();
+            if !pool.shared.contains(thread_id) { clusters.push(None); continue; }
+
+            let mut cluster = match previous {
+                Some(previous) => Cluster::from_pool(previous, pool.shared.clone()),
+                None => {
+                    let mut cluster = Cluster::new(thread_id, pool.cluster_capacity, pool.shared.clone());
+                    cluster.set_generation(pool.generations.of(thread_id));
+                    cluster
+                },
+            };
+            cluster.scheduler.sequential = true;
+            let wake_signal = WakeSignal::default();
+            cluster.events = events.clone();
+            cluster.event_queue = Some(events.connect(thread_id, wake_signal.clone()));
+            cluster.idle_strategy = pool.idle_strategy;
+            cluster.wake_signal = wake_signal;
+
+            let group = pool.group(thread_id);
+            let (setup, opperation) = group.map_or((setup, opperation), |group| (group.setup, group.update));
+            if let Some(group) = group {
+                cluster.group = Some(group.name);
+                cluster.tick_rate = group.tick_rate;
+            }
+            (setup)(&mut cluster);
+            clusters.push(Some(SimulatedCluster { cluster, opperation, tasks: Vec::new(), ticked_at: 0.0 }));
+        }
+
+        Some(Simulation {
+            shared: pool.shared.clone(),
+            cluster_capacity: pool.cluster_capacity,
+            clusters, events,
+            generations: pool.generations.clone(),
+            time: 0.0, ticks: 0,
+        })
+    }
+
+    /// Seconds of virtual time that passed.
+    pub fn time(&self) -> f64 { self.time }
+    /// Number of steps taken.
+    pub fn ticks(&self) -> u64 { self.ticks }
+
+    /// Advances virtual time and ticks every cluster whose tick is due, with
+    /// the time since its last tick as delta time. Clusters with a tick rate
+    /// wait for their next tick, reactive clusters for a wake-up or a timer.
+    pub fn step(&mut self, delta_time: f32) {
+        self.time += delta_time as f64;
+        self.ticks += 1;
+
+        for thread_id in 0..self.clusters.len() {
+            let Some(simulated) = self.clusters[thread_id].as_mut() else { continue; };
+            let elapsed = (self.time - simulated.ticked_at) as f32;
+            let cluster = &mut simulated.cluster;
+
+            if let Some(tick_rate) = cluster.tick_rate.filter(|rate| *rate > 0.0) {
+                // allows for rounding when summing up virtual time
+                if elapsed + 1e-6 < 1.0 / tick_rate { continue; }
+            }
+            if cluster.idle_strategy == IdleStrategy::Reactive
+            && simulated.tasks.is_empty()
+            && !cluster.wake_signal.take()
+            && cluster.until_next_timer().is_none_or(|due| due > elapsed) {
+                continue;
+            }
+            simulated.ticked_at = self.time;
+
+            let tasks = std::mem::take(&mut simulated.tasks);
+            if simulated.cluster.tick(tasks.into_iter(), simulated.opperation, &elapsed).is_none() {
+                self.clusters[thread_id] = None;
+                self.generations.bump(thread_id);
+                self.shared.remove_cell(thread_id);
+            }
+        }
+    }
+
+    /// Takes the given number of steps of the same delta time.
+    pub fn run(&mut self, ticks: u64, delta_time: f32) {
+        for _tick in 0..ticks { self.step(delta_time); }
+    }
+
+    pub fn cluster(&mut self, thread_id: usize) -> Option<&mut Cluster<PoolItem, LocalData>> {
+        self.clusters.get_mut(thread_id)?.as_mut().map(|simulated| &mut simulated.cluster)
+    }
+
+    /// Queues a task that the cluster runs at the start of its next tick,
+    /// like `ThreadPool::with_cluster`. Returns false if there is no such cluster.
+    pub fn with_cluster<F>(&mut self, thread_id: usize, task: F) -> bool
+    where   F: FnOnce(&mut Cluster<PoolItem, LocalData>) + Send + 'static,
+    {
+        match self.clusters.get_mut(thread_id).and_then(Option::as_mut) {
+            Some(simulated) => { simulated.tasks.push(Box::new(task)); true },
+            None => false,
+        }
+    }
+
+    /// Sends an event to every cluster, delivered at their next tick.
+    pub fn publish<E: std::any::Any + Send + Sync>(&self, event: E) -> bool {
+        self.events.publish(event)
+    }
+
+    pub fn snapshot(&self) -> PoolSnapshot<PoolItem, LocalData> {
+        let pools = self.clusters.iter().enumerate()
+            .map(|(thread_id, simulated)| match simulated {
+                Some(simulated) => simulated.cluster.pool_with_lifetimes(),
+                None => self.generations.vacant_pool(thread_id, 0),
+            })
+            .collect();
+        let removed = self.clusters.iter().enumerate()
+            .filter_map(|(thread_id, simulated)| simulated.is_none().then_some(thread_id))
+            .collect();
+
+        PoolSnapshot {
+            cluster_capacity: self.cluster_capacity,
+            pools,
+            shared: (0..self.clusters.len()).map(|thread_id| self.shared.unlinked(thread_id)).collect(),
+            removed,
+        }
+    }
+}
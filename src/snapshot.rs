@@ -0,0 +1,61 @@
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::ObjectPool;
+
+/// Copy of the full state of a `ThreadPool`: one `ObjectPool` per cluster
+/// and the contents of every `DataManager` cell, indexed by thread id.
+/// Removed clusters keep their thread id with an empty pool.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PoolSnapshot<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send,
+{
+    pub cluster_capacity: u32,
+    pub pools: Vec<ObjectPool<PoolItem>>,
+    pub shared: Vec<LocalData>,
+    // thread ids of removed clusters
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub removed: Vec<usize>,
+}
+
+impl<PoolItem, LocalData> PoolSnapshot<PoolItem, LocalData>
+where   PoolItem: Default + Clone + Send,
+{
+    pub fn cluster_count(&self) -> usize { self.pools.len() }
+
+    /// Checks that the snapshot describes a pool that can be restored.
+    pub fn validate(&self) -> Result<(), SnapshotError> {
+        if self.pools.len() > u8::MAX as usize {
+            return Err(SnapshotError::TooManyClusters(self.pools.len()));
+        }
+        if self.pools.len() != self.shared.len() {
+            return Err(SnapshotError::SharedCountMismatch { pools: self.pools.len(), shared: self.shared.len() });
+        }
+        if let Some(index) = (0..self.pools.len()).find(|i| *self.pools[*i].thread_id() != *i) {
+            return Err(SnapshotError::ThreadIdMismatch { index, thread_id: *self.pools[index].thread_id() });
+        }
+        if let Some(thread_id) = self.removed.iter().find(|thread_id| **thread_id >= self.pools.len()) {
+            return Err(SnapshotError::UnknownRemoved(*thread_id));
+        }
+        if let Some(thread_id) = (0..self.pools.len()).find(|i| !self.pools[*i].is_consistent()) {
+            return Err(SnapshotError::InconsistentPool(thread_id));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+    /// More clusters than a `ThreadPool` can hold.
+    TooManyClusters(usize),
+    /// There is not exactly one shared data cell per pool.
+    SharedCountMismatch { pools: usize, shared: usize },
+    /// The pool at the index belongs to another thread id.
+    ThreadIdMismatch { index: usize, thread_id: usize },
+    /// A removed thread id without a pool.
+    UnknownRemoved(usize),
+    /// The pool of the thread id has items, spawns and free or active slots
+    /// that do not add up, as only a corrupted snapshot can.
+    InconsistentPool(usize),
+}
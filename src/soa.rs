@@ -0,0 +1,135 @@
+use crate::{Spawn, pooling::{NO_SPAWN, POOL_TAG_SHIFT, SOA_POOL_TAG}};
+
+/// Item that can be stored as struct-of-arrays, one column per field.
+/// Implemented by `#[derive(SoA)]` with the `soa` feature.
+pub trait SoaItem: Default + Clone + Send + 'static {
+    type Columns: Default + Clone + Send;
+    type Slices<'a>;
+    type SlicesMut<'a>;
+
+    fn push(columns: &mut Self::Columns, item: Self);
+    fn read(columns: &Self::Columns, index: usize) -> Self;
+    fn write(columns: &mut Self::Columns, index: usize, item: Self);
+    fn swap(columns: &mut Self::Columns, a: usize, b: usize);
+    fn slices(columns: &Self::Columns, len: usize) -> Self::Slices<'_>;
+    fn slices_mut(columns: &mut Self::Columns, len: usize) -> Self::SlicesMut<'_>;
+}
+
+/// Pool storing every field of its items in a separate column. Active items
+/// are kept at the front of the columns, so `slices` and `slices_mut` cover
+/// exactly the active items. Spawns resolve through a slot table and stay
+/// valid while items move around.
+///
+/// This is a standalone container, clusters keep their items in an 
+/// `ObjectPool` and can not use it as their layout. Its spawns carry a pool
+/// tag of their own, so they never validate in the pools of a cluster and
+/// the other way around, but no cluster generation.
+#[derive(Clone)]
+pub struct SoaPool<ItemType: SoaItem> {
+    on_thread: usize,
+
+    columns: ItemType::Columns,
+    capacity: usize,
+    active_count: usize,
+    spawn_id_counter: u128,
+
+    // one spawn per slot, handed out unchanged so spawns compare equal
+    slots: Vec<Spawn>,
+    // column of the item of each slot, and slot owning each column
+    column_of: Vec<usize>,
+    slot_of: Vec<usize>,
+    free_slots: Vec<usize>,
+}
+
+impl<ItemType: SoaItem> SoaPool<ItemType> {
+    pub fn new(on_thread: usize, capacity: u32) -> Self {
+        let capacity = capacity as usize;
+        let mut columns = ItemType::Columns::default();
+        for _i in 0..capacity { ItemType::push(&mut columns, ItemType::default()); }
+
+        SoaPool {
+            on_thread,
+            columns, capacity,
+            active_count: 0, spawn_id_counter: (SOA_POOL_TAG as u128) << POOL_TAG_SHIFT,
+            slots: (0..capacity).map(|i| Spawn{ thread_id: on_thread, id: NO_SPAWN, self_index: i, pool_index: i }).collect(),
+            column_of: (0..capacity).collect(),
+            slot_of: (0..capacity).collect(),
+            free_slots: (0..capacity).rev().collect(),
+        }
+    }
+
+    pub fn thread_id(&self) -> &usize { &self.on_thread }
+
+    pub fn capacity(&self) -> usize { self.capacity }
+    pub fn count(&self) -> usize { self.active_count }
+
+    /// Whether the spawn belongs to this pool and its item was not destroyed.
+    pub fn is_valid(&self, spawn: &Spawn) -> bool {
+        spawn.thread_id == self.on_thread
+        && self.slots.get(spawn.self_index).is_some_and(|s| s.id == spawn.id)
+    }
+
+    /// Column index of the item, valid until an item is destroyed.
+    pub fn index_of(&self, spawn: &Spawn) -> Option<usize> {
+        self.is_valid(spawn).then(|| self.column_of[spawn.self_index])
+    }
+
+    /// Spawn of the active item at a column index.
+    pub fn spawn_at(&self, index: usize) -> Option<&Spawn> {
+        (index < self.active_count).then(|| &self.slots[self.slot_of[index]])
+    }
+
+    /// Copy of the item, gathered from all columns.
+    pub fn get(&self, spawn: &Spawn) -> Option<ItemType> {
+        self.index_of(spawn).map(|index| ItemType::read(&self.columns, index))
+    }
+
+    /// Overwrites the item, returns false if the spawn is not valid.
+    pub fn set(&mut self, spawn: &Spawn, item: ItemType) -> bool {
+        match self.index_of(spawn) {
+            Some(index) => { ItemType::write(&mut self.columns, index, item); true },
+            None => false,
+        }
+    }
+
+    /// Spawns a default item at the end of the active items.
+    pub fn spawn(&mut self) -> Option<Spawn> {
+        let slot = self.free_slots.pop()?;
+        let index = self.active_count;
+
+        ItemType::write(&mut self.columns, index, ItemType::default());
+        self.slots[slot].id = self.spawn_id_counter;
+        self.column_of[slot] = index;
+        self.slot_of[index] = slot;
+
+        self.spawn_id_counter += 1;
+        self.active_count += 1;
+        Some(self.slots[slot].clone())
+    }
+
+    /// Destroys an item in constant time, the last active item takes its place.
+    pub fn destroy(&mut self, spawn: Spawn) {
+        let Some(index) = self.index_of(&spawn) else { return; };
+        let last = self.active_count - 1;
+
+        ItemType::swap(&mut self.columns, index, last);
+        let moved = self.slot_of[last];
+        self.column_of[moved] = index;
+        self.slot_of[index] = moved;
+        self.slot_of[last] = spawn.self_index;
+
+        self.slots[spawn.self_index].id = NO_SPAWN;
+        self.free_slots.push(spawn.self_index);
+        self.active_count = last;
+    }
+
+    /// One slice per field over the active items.
+    pub fn slices(&self) -> ItemType::Slices<'_> {
+        ItemType::slices(&self.columns, self.active_count)
+    }
+
+    /// One mutable slice per field over the active items.
+    pub fn slices_mut(&mut self) -> ItemType::SlicesMut<'_> {
+        ItemType::slices_mut(&mut self.columns, self.active_count)
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use crate::{ObjectPool, Spawn};
+
+pub type PositionAccessor<ItemType> = fn(&ItemType) -> [f32; 3];
+
+type CellKey = [i32; 3];
+
+/// Uniform grid of pooled items, keyed by the position the accessor returns
+/// for each item. 2D users can simply return 0.0 as third coordinate.
+pub struct SpatialGrid<ItemType> {
+    cell_size: f32,
+    position: PositionAccessor<ItemType>,
+    cells: HashMap<CellKey, Vec<Spawn>>,
+    // indexed by pool_index
+    tracked: Vec<Option<(Spawn, CellKey)>>,
+    // items spawned or changed since the last update
+    moved: Vec<Spawn>,
+    // set when any item may have changed, the next update then looks at all of them
+    rescan: bool,
+}
+
+impl<ItemType> SpatialGrid<ItemType>
+where   ItemType: Default + Clone + Send,
+{
+    pub fn new(cell_size: f32, position: PositionAccessor<ItemType>) -> Self {
+        SpatialGrid { cell_size, position, cells: HashMap::new(), tracked: Vec::new(), moved: Vec::new(), rescan: true }
+    }
+
+    pub fn cell_size(&self) -> f32 { self.cell_size }
+
+    pub fn cell_of(&self, position: [f32; 3]) -> [i32; 3] {
+        position.map(|p| (p / self.cell_size).floor() as i32)
+    }
+
+    /// Number of items currently in the grid.
+    pub fn len(&self) -> usize { self.cells.values().map(|cell| cell.len()).sum() }
+    pub fn is_empty(&self) -> bool { self.cells.is_empty() }
+
+    /// Marks an item as spawned or moved, the next update only looks at the
+    /// items marked since the last one.
+    pub fn moved(&mut self, spawn: &Spawn) {
+        if self.rescan { return; }
+        // more marks than items, a rescan is cheaper
+        if self.moved.len() >= self.tracked.len().max(64) { self.rescan(); return; }
+        self.moved.push(spawn.clone());
+    }
+
+    /// Lets the next update look at every item of the pool, for when any of
+    /// them may have moved or been destroyed without the grid being told.
+    pub fn rescan(&mut self) {
+        self.rescan = true;
+        self.moved.clear();
+    }
+
+    /// Brings the grid up to date with the items marked since the last
+    /// update, adding newly spawned items, dropping destroyed ones and moving
+    /// items whose position left their cell. After a rescan was requested
+    /// all items of the pool are looked at instead.
+    pub fn update(&mut self, pool: &ObjectPool<ItemType>) {
+        if self.tracked.len() < pool.capacity() {
+            self.tracked.resize(pool.capacity(), None);
+        }
+        if std::mem::take(&mut self.rescan) {
+            self.update_all(pool);
+            return;
+        }
+        for spawn in std::mem::take(&mut self.moved) {
+            if pool.is_valid(&spawn) {
+                self.place(pool, &spawn);
+            } else {
+                self.remove(&spawn);
+            }
+        }
+    }
+
+    // looks at every tracked slot and every active item
+    fn update_all(&mut self, pool: &ObjectPool<ItemType>) {
+        // items destroyed or recycled without the grid being told
+        for slot in 0..self.tracked.len() {
+            let Some((tracked, tracked_cell)) = self.tracked[slot].take() else { continue; };
+            if pool.is_valid(&tracked) {
+                self.tracked[slot] = Some((tracked, tracked_cell));
+            } else {
+                self.remove_from_cell(&tracked, &tracked_cell);
+            }
+        }
+        for active_index in 0..pool.count() {
+            self.place(pool, pool.active_spawn(active_index));
+        }
+    }
+
+    // adds a valid item or moves it to the cell of its current position
+    fn place(&mut self, pool: &ObjectPool<ItemType>, spawn: &Spawn) {
+        let cell = self.cell_of((self.position)(pool.item(spawn)));
+
+        match self.tracked[spawn.pool_index].take() {
+            Some((tracked, tracked_cell)) => if tracked != *spawn || tracked_cell != cell {
+                self.remove_from_cell(&tracked, &tracked_cell);
+                self.add_to_cell(spawn, cell);
+            },
+            None => self.add_to_cell(spawn, cell),
+        }
+        self.tracked[spawn.pool_index] = Some((spawn.clone(), cell));
+    }
+
+    /// Removes an item from the grid, call this when it is destroyed.
+    pub fn remove(&mut self, spawn: &Spawn) {
+        if let Some(Some((tracked, cell))) = self.tracked.get(spawn.pool_index) {
+            if tracked == spawn {
+                let cell = *cell;
+                self.remove_from_cell(spawn, &cell);
+                self.tracked[spawn.pool_index] = None;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.moved.clear();
+        self.rescan = false;
+        self.tracked.iter_mut().for_each(|tracked| *tracked = None);
+    }
+
+    /// Collects the items inside the axis aligned box between min and max.
+    pub fn query_aabb(&self, pool: &ObjectPool<ItemType>, min: [f32; 3], max: [f32; 3], found: &mut Vec<Spawn>) {
+        self.visit_cells(pool, min, max, |spawn| {
+            let p = (self.position)(pool.item(spawn));
+            if (0..3).all(|i| p[i] >= min[i] && p[i] <= max[i]) {
+                found.push(spawn.clone());
+            }
+        });
+    }
+
+    /// Collects the items within radius of center.
+    pub fn query_radius(&self, pool: &ObjectPool<ItemType>, center: [f32; 3], radius: f32, found: &mut Vec<Spawn>) {
+        let min = center.map(|c| c - radius);
+        let max = center.map(|c| c + radius);
+
+        self.visit_cells(pool, min, max, |spawn| {
+            let p = (self.position)(pool.item(spawn));
+            let distance_sq: f32 = (0..3).map(|i| (p[i] - center[i]) * (p[i] - center[i])).sum();
+            if distance_sq <= radius * radius {
+                found.push(spawn.clone());
+            }
+        });
+    }
+
+    // visits the items in the cells between min and max, skipping items 
+    // destroyed since the last update
+    fn visit_cells(&self, pool: &ObjectPool<ItemType>, min: [f32; 3], max: [f32; 3], mut visitor: impl FnMut(&Spawn)) {
+        let (from, to) = (self.cell_of(min), self.cell_of(max));
+        let box_cells = (0..3).map(|i| (to[i] as i64 - from[i] as i64 + 1).max(0) as u128).product::<u128>();
+
+        // boxes covering more cells than are occupied walk the occupied ones
+        if box_cells > self.cells.len() as u128 {
+            for (key, cell) in self.cells.iter() {
+                if (0..3).all(|i| key[i] >= from[i] && key[i] <= to[i]) {
+                    cell.iter().filter(|spawn| pool.is_valid(spawn)).for_each(&mut visitor);
+                }
+            }
+            return;
+        }
+        for x in from[0]..=to[0] {
+            for y in from[1]..=to[1] {
+                for z in from[2]..=to[2] {
+                    if let Some(cell) = self.cells.get(&[x, y, z]) {
+                        cell.iter().filter(|spawn| pool.is_valid(spawn)).for_each(&mut visitor);
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_to_cell(&mut self, spawn: &Spawn, cell: CellKey) {
+        self.cells.entry(cell).or_default().push(spawn.clone());
+    }
+
+    fn remove_from_cell(&mut self, spawn: &Spawn, cell: &CellKey) {
+        if let Some(spawns) = self.cells.get_mut(cell) {
+            if let Some(index) = spawns.iter().position(|s| s == spawn) {
+                spawns.swap_remove(index);
+            }
+            if spawns.is_empty() { self.cells.remove(cell); }
+        }
+    }
+}
@@ -1,20 +1,21 @@
-#[cfg(test)]
+// lints the style of the original tests would trip
+#![allow(clippy::bool_assert_comparison, clippy::assign_op_pattern, unused_mut)]
 
-#[warn(unused_imports)]
 use std::{
     time::Duration,
-    sync::{Arc, Mutex}, 
-    thread::{self}
+    thread::{self},
+    sync::atomic::Ordering,
 };
 
-#[warn(unused_imports)]
-use crate::{DataCell, ObjectPool, DataManager};
+use crate::{DataManager, ObjectPool};
+#[cfg(feature = "serde")]
+use crate::PoolSnapshot;
 
 #[allow(unused)]
 use super::{ThreadPool, Cluster, Spawn};
 
 #[derive(Default, Clone)]
-struct PoolObject(bool);
+struct PoolObject(#[allow(unused)] bool);
 
 #[allow(unused)]
 #[derive(Default, Clone, Debug)]
@@ -32,29 +33,29 @@ fn start_stop_threads() {
         |_c, _dt|{}
     );
     thread::sleep(Duration::from_millis(10));
-    assert!(*thread_pool.run_handle.lock().unwrap());
+    assert!(thread_pool.run_handle.load(Ordering::Acquire));
     
     thread_pool.stop();
     thread::sleep(Duration::from_millis(10));
-    assert!(!*thread_pool.run_handle.lock().unwrap());
+    assert!(!thread_pool.run_handle.load(Ordering::Acquire));
 
     thread_pool.start(
         |_c|{}, 
         |_c, _dt|{}
     );
     thread::sleep(Duration::from_millis(10));
-    assert!(*thread_pool.run_handle.lock().unwrap());
+    assert!(thread_pool.run_handle.load(Ordering::Acquire));
 
     thread_pool.start(
         |_c|{}, 
         |_c, _dt|{}
     );
     thread::sleep(Duration::from_millis(10));
-    assert!(*thread_pool.run_handle.lock().unwrap());
+    assert!(thread_pool.run_handle.load(Ordering::Acquire));
 
     thread_pool.stop();
     thread::sleep(Duration::from_millis(10));
-    assert!(!*thread_pool.run_handle.lock().unwrap());
+    assert!(!thread_pool.run_handle.load(Ordering::Acquire));
 }
 
 #[test]
@@ -169,11 +170,11 @@ fn clusters_can_spawn_objects() {
     assert_eq!(cluster.count(), 0);
 
     let spawn_1 = cluster.spawn();
-    assert_eq!(spawn_1, Some(Spawn{ id: 0, self_index: 0, pool_index: 0}));
+    assert_eq!(spawn_1, Some(Spawn{ thread_id: 0, id: 0, self_index: 0, pool_index: 0}));
     assert_eq!(cluster.count(), 1);
 
     let spawn_2 = cluster.spawn();
-    assert_eq!(spawn_2, Some(Spawn{ id: 1, self_index: 1, pool_index: 1}));
+    assert_eq!(spawn_2, Some(Spawn{ thread_id: 0, id: 1, self_index: 1, pool_index: 1}));
     assert_eq!(cluster.count(), 2);
 
     let spawn_3 = cluster.spawn();
@@ -197,7 +198,7 @@ fn clusters_can_destroy_objects() {
 
     let spawn_3 = cluster.spawn().unwrap();
     assert_ne!(spawn_1, spawn_3);
-    assert_eq!(spawn_3, Spawn{ id:2, self_index:0, pool_index:0 });
+    assert_eq!(spawn_3, Spawn{ thread_id: 0, id:2, self_index:0, pool_index:0 });
 }
 
 #[test]
@@ -231,4 +232,1122 @@ fn clusters_have_factories_for_spawning_specific_types() {
 
     assert_eq!(cluster.fetch(&spawn_1), Some(&mut false));
     assert_eq!(cluster.fetch(&spawn_2), Some(&mut true));
-}
\ No newline at end of file
+}
+#[test]
+fn spawns_stay_valid_after_recycling_other_items() {
+    let mut cluster = Cluster::<bool, bool>::new(0, 2, DataManager::new(1));
+
+    let spawn_1 = cluster.spawn().unwrap();
+    let spawn_2 = cluster.spawn().unwrap();
+    *cluster.fetch(&spawn_2).unwrap() = true;
+
+    cluster.destroy(spawn_1);
+    let spawn_3 = cluster.spawn().unwrap();
+
+    assert_eq!(cluster.fetch(&spawn_2), Some(&mut true));
+    assert_eq!(cluster.fetch(&spawn_3), Some(&mut false));
+}
+
+#[test]
+fn stopped_pools_can_be_restored_from_a_snapshot() {
+    use crate::SnapshotError;
+
+    let mut thread_pool = ThreadPool::<u32, u32>::new(2, 4);
+
+    thread_pool.start(
+        |c|{ 
+            let spawn = c.spawn().unwrap();
+            *c.fetch(&spawn).unwrap() = 10 + *c.thread_id() as u32;
+        }, 
+        |c, _dt|{ c.shared.write(*c.thread_id(), |d| *d = 1); }
+    );
+    thread::sleep(Duration::from_millis(10));
+    assert!(thread_pool.snapshot().is_none());
+
+    thread_pool.stop();
+    let snapshot = thread_pool.snapshot().unwrap();
+    assert_eq!(snapshot.cluster_count(), 2);
+    assert_eq!(snapshot.shared, vec![1, 1]);
+    assert_eq!(snapshot.pools[1].count(), 1);
+
+    // snapshots that do not describe a pool are refused
+    let mut broken = snapshot.clone();
+    broken.shared.pop();
+    assert_eq!(ThreadPool::from_snapshot(broken).err(), Some(SnapshotError::SharedCountMismatch { pools: 2, shared: 1 }));
+    let mut broken = snapshot.clone();
+    broken.pools.swap(0, 1);
+    assert_eq!(ThreadPool::from_snapshot(broken).err(), Some(SnapshotError::ThreadIdMismatch { index: 0, thread_id: 1 }));
+    let mut broken = snapshot.clone();
+    broken.removed.push(2);
+    assert_eq!(ThreadPool::from_snapshot(broken).err(), Some(SnapshotError::UnknownRemoved(2)));
+    let mut broken = snapshot.clone();
+    broken.pools[1].items.pop();
+    assert_eq!(ThreadPool::from_snapshot(broken).err(), Some(SnapshotError::InconsistentPool(1)));
+    let mut broken = snapshot.clone();
+    broken.pools[1].all_spawns[0].id = crate::pooling::NO_SPAWN;
+    assert_eq!(ThreadPool::from_snapshot(broken).err(), Some(SnapshotError::InconsistentPool(1)));
+    let mut broken = snapshot.clone();
+    broken.pools[0].active_pool_count += 1;
+    assert_eq!(ThreadPool::from_snapshot(broken).err(), Some(SnapshotError::InconsistentPool(0)));
+
+    let spawn = Spawn{ thread_id: 1, id: 0, self_index: 0, pool_index: 0 };
+    let mut restored = ThreadPool::from_snapshot(snapshot).unwrap();
+    let mut restored_snapshot = restored.snapshot().unwrap();
+    assert_eq!(restored.shared.unlinked(1), 1);
+    assert_eq!(restored_snapshot.pools[1].fetch(&spawn), Some(&mut 11));
+
+    restored.start(
+        |c|{ 
+            let spawn = Spawn{ thread_id: *c.thread_id(), id: 0, self_index: 0, pool_index: 0 };
+            let value = *c.fetch(&spawn).unwrap();
+            c.shared.write(*c.thread_id(), |d| *d = 0);
+            c.shared.catch(*c.thread_id(), &value, |v, d| *d = *v);
+        }, 
+        |_c, _dt|{}
+    );
+    thread::sleep(Duration::from_millis(10));
+    restored.stop();
+    assert_eq!(restored.shared.unlinked_all(), vec![10, 11]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn snapshots_can_be_serialized() {
+    let mut pool = ObjectPool::<u32>::new(0, 3);
+    let spawn_1 = pool.spawn().unwrap();
+    let spawn_2 = pool.spawn().unwrap();
+    *pool.fetch(&spawn_2).unwrap() = 7;
+    pool.destroy(spawn_1.clone());
+
+    let shared = DataManager::<u32>::new(1);
+    let snapshot = PoolSnapshot { cluster_capacity: 3, pools: vec![pool], shared: shared.unlinked_all(), removed: Vec::new() };
+
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let restored: PoolSnapshot<u32, u32> = serde_json::from_str(&json).unwrap();
+    let mut pool = restored.pools[0].clone();
+
+    assert_eq!(pool.count(), 1);
+    assert_eq!(pool.fetch(&spawn_2), Some(&mut 7));
+    assert_eq!(pool.spawn().unwrap().id, 2);
+
+    let cluster = Cluster::<u32, u32>::new(0, 2, DataManager::new(2));
+    let json = serde_json::to_string(&cluster).unwrap();
+    let restored: Cluster<u32, u32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.capacity(), 2);
+    assert_eq!(restored.shared.unlinked_all(), vec![0, 0]);
+}
+
+#[test]
+fn running_pools_can_be_checkpointed() {
+    let mut thread_pool = ThreadPool::<u32, u32>::new(3, 4);
+
+    thread_pool.start(
+        |c|{ c.spawn(); }, 
+        |c, _dt|{ 
+            c.iter(|pool, _shared| *pool.target() += 1);
+            c.shared.write(*c.thread_id(), |d| *d += 1);
+        }
+    );
+    thread::sleep(Duration::from_millis(10));
+
+    let snapshot = thread_pool.checkpoint().unwrap();
+    assert!(thread_pool.is_running());
+    assert_eq!(snapshot.cluster_count(), 3);
+
+    for (i, mut pool) in snapshot.pools.into_iter().enumerate() {
+        let spawn = Spawn{ thread_id: i, id: 0, self_index: 0, pool_index: 0 };
+        assert_eq!(*pool.thread_id(), i);
+        assert_eq!(pool.fetch(&spawn), Some(&mut snapshot.shared[i].clone()));
+    }
+
+    // clusters keep running after a checkpoint
+    let mut later = thread_pool.checkpoint().unwrap();
+    for _ in 0..100 {
+        if later.shared[0] > snapshot.shared[0] { break; }
+        thread::sleep(Duration::from_millis(10));
+        later = thread_pool.checkpoint().unwrap();
+    }
+    assert!(later.shared[0] > snapshot.shared[0]);
+
+    thread_pool.stop();
+}
+
+#[cfg(feature = "replay")]
+#[test]
+fn recorded_clusters_can_be_replayed() {
+    use crate::{ClusterInput, Recording, ReplayError};
+
+    fn update(c: &mut Cluster<u32, u32>, _dt: &f32) {
+        if *c.thread_id() == 0 {
+            c.iter(|pool, shared| *pool.target() += shared.unlinked(0));
+        } else {
+            c.shared.write(0, |d| *d += 1);
+        }
+    }
+
+    let mut thread_pool = ThreadPool::<u32, u32>::new(2, 8);
+    thread_pool.start_recording(|_c|{}, update);
+    thread::sleep(Duration::from_millis(5));
+    thread_pool.input(0, ClusterInput::Spawn);
+    thread_pool.input(0, ClusterInput::Spawn);
+    thread::sleep(Duration::from_millis(5));
+    thread_pool.input(0, ClusterInput::Destroy(Spawn{ thread_id: 0, id: 0, self_index: 0, pool_index: 0 }));
+    thread::sleep(Duration::from_millis(5));
+    thread_pool.stop();
+
+    let recording = thread_pool.take_recording().unwrap();
+    assert!(recording.frame_count(0) > 0);
+    let expected = thread_pool.snapshot().unwrap();
+
+    let bytes = recording.to_bytes();
+    assert_eq!(Recording::<u32, u32>::from_bytes(&bytes[1..]).err(), Some(ReplayError::InvalidLog));
+    let recording = Recording::<u32, u32>::from_bytes(&bytes).unwrap();
+
+    let mut replayed = ThreadPool::<u32, u32>::new(2, 8);
+    assert_eq!(replayed.replay(recording, |_c|{}, update), Ok(()));
+    let mut result = replayed.snapshot().unwrap();
+
+    let spawn = Spawn{ thread_id: 0, id: 1, self_index: 1, pool_index: 1 };
+    assert_eq!(result.pools[0].count(), 1);
+    assert_eq!(result.pools[0].fetch(&spawn).copied(), expected.pools[0].clone().fetch(&spawn).copied());
+    assert!(*result.pools[0].fetch(&spawn).unwrap() > 0);
+}
+
+#[cfg(feature = "replay")]
+#[test]
+fn recordings_with_tasks_or_events_are_not_replayed() {
+    use crate::{ClusterInput, ReplayError};
+
+    struct Ping;
+
+    let mut thread_pool = ThreadPool::<u32, u32>::new(2, 8);
+    thread_pool.start_recording(|_c|{}, |_c, _dt|{});
+    thread_pool.input(0, ClusterInput::Spawn);
+    assert_eq!(thread_pool.item_counts(), vec![1, 0]);
+    thread_pool.stop();
+    let recording = thread_pool.take_recording().unwrap();
+    assert_eq!(ThreadPool::<u32, u32>::new(2, 8).replay(recording, |_c|{}, |_c, _dt|{}), Ok(()));
+
+    thread_pool.start_recording(|_c|{}, |_c, _dt|{});
+    let reply = thread_pool.with_cluster_reply(1, |c| c.spawn()).unwrap();
+    assert!(reply.recv().unwrap().is_some());
+    thread_pool.stop();
+    let recording = thread_pool.take_recording().unwrap();
+    let replayed = ThreadPool::<u32, u32>::new(2, 8).replay(recording, |_c|{}, |_c, _dt|{});
+    assert!(matches!(replayed, Err(ReplayError::Unrecorded { thread_id: 1, .. })));
+
+    thread_pool.start_recording(|c| c.subscribe::<Ping>(|c, _ping| { c.spawn(); }), |_c, _dt|{});
+    thread_pool.publish(Ping);
+    thread::sleep(Duration::from_millis(10));
+    thread_pool.stop();
+    let recording = thread_pool.take_recording().unwrap();
+    let replayed = ThreadPool::<u32, u32>::new(2, 8).replay(recording, |_c|{}, |_c, _dt|{});
+    assert!(matches!(replayed, Err(ReplayError::Unrecorded { .. })));
+}
+
+#[cfg(feature = "replay")]
+#[test]
+fn recordings_include_shared_reads_of_scheduled_systems() {
+    use crate::{System, Resource};
+
+    #[derive(Default, Clone, Debug)]
+    struct Seen(u32);
+
+    fn setup(c: &mut Cluster<u32, u32>) {
+        if *c.thread_id() != 0 { return; }
+        c.init_world(1);
+        c.world.register::<Seen>();
+        let entity = c.world.spawn().unwrap();
+        c.world.insert(&entity, Seen(0));
+        c.spawn();
+        c.schedule(System {
+            name: "observe",
+            reads: vec![Resource::Shared(1)],
+            writes: vec![Resource::component::<Seen>()],
+            run: |ctx, _dt| {
+                let value = ctx.read_shared(1).unwrap();
+                ctx.write::<Seen>().unwrap().for_each(|_e, seen| seen.0 = value);
+            },
+        }).unwrap();
+    }
+    fn update(c: &mut Cluster<u32, u32>, _dt: &f32) {
+        if *c.thread_id() == 0 {
+            let mut seen = 0;
+            c.world.query_one::<Seen>(|_e, s| seen = s.0);
+            *c.fetch(&Spawn{ thread_id: 0, id: 0, self_index: 0, pool_index: 0 }).unwrap() = seen;
+        } else {
+            c.shared.write(1, |d| *d += 1);
+        }
+    }
+
+    let mut thread_pool = ThreadPool::<u32, u32>::new(2, 8);
+    thread_pool.start_recording(setup, update);
+    thread::sleep(Duration::from_millis(20));
+    thread_pool.stop();
+
+    let recording = thread_pool.take_recording().unwrap();
+    let mut expected = thread_pool.snapshot().unwrap();
+    let mut replayed = ThreadPool::<u32, u32>::new(2, 8);
+    assert_eq!(replayed.replay(recording, setup, update), Ok(()));
+    let mut result = replayed.snapshot().unwrap();
+
+    let spawn = Spawn{ thread_id: 0, id: 0, self_index: 0, pool_index: 0 };
+    assert!(*expected.pools[0].fetch(&spawn).unwrap() > 0);
+    assert_eq!(result.pools[0].fetch(&spawn), expected.pools[0].fetch(&spawn));
+}
+
+#[test]
+fn clusters_can_pool_multiple_item_types() {
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Bullet(u32);
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Particle(f32);
+
+    let mut cluster = Cluster::<bool, bool>::new(0, 2, DataManager::new(1));
+    assert_eq!(cluster.pools.spawn::<Bullet>(), None);
+
+    cluster.pools.register::<Bullet>(3);
+    cluster.pools.register::<Particle>(1);
+    assert!(cluster.pools.is_registered::<Bullet>());
+
+    let bullet_1 = cluster.pools.spawn::<Bullet>().unwrap();
+    let bullet_2 = cluster.pools.spawn::<Bullet>().unwrap();
+    let particle = cluster.pools.spawn::<Particle>().unwrap();
+    assert_eq!(cluster.pools.spawn::<Particle>(), None);
+    assert_eq!(cluster.count(), 0);
+
+    cluster.pools.fetch::<Bullet>(&bullet_2).unwrap().0 = 5;
+    cluster.pools.iter::<Bullet>(|pool| pool.target().0 += 1);
+    cluster.pools.iter::<Particle>(|pool| pool.target().0 = 0.5);
+
+    assert_eq!(cluster.pools.fetch::<Bullet>(&bullet_1), Some(&mut Bullet(1)));
+    assert_eq!(cluster.pools.fetch::<Bullet>(&bullet_2), Some(&mut Bullet(6)));
+    assert_eq!(cluster.pools.fetch::<Particle>(&particle), Some(&mut Particle(0.5)));
+
+    // spawns only validate in the pool that handed them out
+    let item = cluster.spawn().unwrap();
+    assert_eq!(cluster.pools.fetch::<Particle>(&bullet_1), None);
+    assert_eq!(cluster.pools.fetch::<Bullet>(&particle), None);
+    assert_eq!(cluster.pools.fetch::<Bullet>(&item), None);
+    assert_eq!(cluster.fetch(&bullet_1), None);
+
+    cluster.pools.destroy::<Bullet>(bullet_1);
+    assert_eq!(cluster.pools.count::<Bullet>(), 1);
+    assert_eq!(cluster.pools.count::<Particle>(), 1);
+}
+
+#[test]
+fn world_queries_join_components_of_entities() {
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Position(f32);
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Velocity(f32);
+
+    let mut cluster = Cluster::<bool, bool>::new(0, 2, DataManager::new(1));
+    cluster.init_world(4);
+    cluster.world.register::<Position>();
+    cluster.world.register::<Velocity>();
+
+    let moving = cluster.world.spawn().unwrap();
+    let fixed = cluster.world.spawn().unwrap();
+    let ghost = cluster.world.spawn().unwrap();
+    assert!(cluster.world.insert(&moving, Position(1.0)));
+    assert!(cluster.world.insert(&moving, Velocity(2.0)));
+    assert!(cluster.world.insert(&fixed, Position(5.0)));
+    assert!(cluster.world.insert(&ghost, Velocity(9.0)));
+
+    cluster.add_system(|c, dt| {
+        c.world.query::<Position, Velocity>(|_entity, position, velocity| {
+            position.0 += velocity.0 * dt;
+        });
+    });
+    cluster.run_systems(&0.5);
+    cluster.run_systems(&0.5);
+
+    assert_eq!(cluster.world.get::<Position>(&moving), Some(&mut Position(3.0)));
+    assert_eq!(cluster.world.get::<Position>(&fixed), Some(&mut Position(5.0)));
+    assert_eq!(cluster.world.get::<Position>(&ghost), None);
+
+    // spawns of other pools are not entities, even past the end of the world
+    let item = ObjectPool::<bool>::new(0, 8).spawn_many(6).pop().unwrap();
+    assert_eq!(cluster.world.get::<Position>(&item), None);
+    cluster.world.remove::<Position>(&item);
+
+    // entities and the cluster's items never validate in each other's pools
+    let item = cluster.spawn().unwrap();
+    assert_eq!(cluster.fetch(&moving), None);
+    assert_eq!(cluster.world.get::<Position>(&item), None);
+    assert!(!cluster.world.is_alive(&item));
+
+    cluster.world.despawn(moving.clone());
+    assert!(!cluster.world.is_alive(&moving));
+    assert_eq!(cluster.world.get::<Velocity>(&moving), None);
+    assert_eq!(cluster.world.components::<Velocity>().unwrap().count(), 1);
+
+    let mut visited = 0;
+    cluster.world.query_one::<Position>(|_entity, _position| visited += 1);
+    assert_eq!(visited, 1);
+}
+
+#[test]
+fn scheduled_systems_run_in_conflict_free_stages() {
+    use crate::{System, Resource, ScheduleError};
+
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Position(f32);
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Velocity(f32);
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Health(u32);
+
+    let mut cluster = Cluster::<bool, u32>::new(0, 2, DataManager::new(1));
+    cluster.init_world(4);
+    cluster.world.register::<Position>();
+    cluster.world.register::<Velocity>();
+
+    let unknown = cluster.schedule(System {
+        name: "regen", reads: vec![], writes: vec![Resource::component::<Health>()], run: |_ctx, _dt| {},
+    });
+    assert!(matches!(unknown, Err(ScheduleError::UnknownComponent(_))));
+    cluster.world.register::<Health>();
+
+    assert_eq!(cluster.schedule(System {
+        name: "movement",
+        reads: vec![Resource::component::<Velocity>()],
+        writes: vec![Resource::component::<Position>()],
+        run: |ctx, dt| ctx.query::<Position, Velocity>(|_e, p, v| p.0 += v.0 * dt),
+    }), Ok(vec![]));
+    assert_eq!(cluster.schedule(System {
+        name: "regen",
+        reads: vec![],
+        writes: vec![Resource::component::<Health>(), Resource::Shared(0)],
+        run: |ctx, _dt| {
+            ctx.write::<Health>().unwrap().for_each(|_e, h| h.0 += 1);
+            ctx.write_shared(0, |d| *d += 1);
+        },
+    }), Ok(vec![]));
+    assert_eq!(cluster.schedule(System {
+        name: "damping",
+        reads: vec![],
+        writes: vec![Resource::component::<Velocity>()],
+        run: |ctx, _dt| ctx.write::<Velocity>().unwrap().for_each(|_e, v| v.0 *= 0.5),
+    }), Ok(vec!["movement"]));
+    assert_eq!(cluster.schedule(System {
+        name: "damping", reads: vec![], writes: vec![], run: |_ctx, _dt| {},
+    }), Err(ScheduleError::DuplicateName("damping")));
+    assert_eq!(cluster.schedule_stages(), 2);
+
+    let entity = cluster.world.spawn().unwrap();
+    cluster.world.insert(&entity, Position(0.0));
+    cluster.world.insert(&entity, Velocity(2.0));
+    cluster.world.insert(&entity, Health(0));
+
+    cluster.run_systems(&1.0);
+    cluster.run_systems(&1.0);
+
+    assert_eq!(cluster.world.get::<Position>(&entity), Some(&mut Position(3.0)));
+    assert_eq!(cluster.world.get::<Velocity>(&entity), Some(&mut Velocity(0.5)));
+    assert_eq!(cluster.world.get::<Health>(&entity), Some(&mut Health(2)));
+    assert_eq!(cluster.shared.unlinked(0), 2);
+}
+
+#[test]
+fn clusters_can_iter_sorted_and_filtered() {
+    let mut cluster = Cluster::<u32, Vec<u32>>::new(0, 4, DataManager::new(1));
+    for value in [30, 10, 20, 10] {
+        let spawn = cluster.spawn().unwrap();
+        *cluster.fetch(&spawn).unwrap() = value;
+    }
+    cluster.destroy(Spawn{ thread_id: 0, id: 0, self_index: 0, pool_index: 0 });
+    let spawn = cluster.spawn().unwrap();
+    *cluster.fetch(&spawn).unwrap() = 5;
+
+    fn collect(pool: &mut ObjectPool<u32>, shared: &mut DataManager<Vec<u32>>) {
+        shared.catch(0, pool.target(), |v, d| d.push(*v));
+    }
+
+    cluster.iter_sorted_by_key(|v| *v, collect);
+    assert_eq!(cluster.shared.unlinked(0), vec![5, 10, 10, 20]);
+
+    cluster.shared.write(0, |d| d.clear());
+    cluster.iter_spawn_order(collect);
+    assert_eq!(cluster.shared.unlinked(0), vec![10, 20, 10, 5]);
+
+    cluster.shared.write(0, |d| d.clear());
+    cluster.iter_where(|v| *v >= 10, collect);
+    assert_eq!(cluster.shared.unlinked(0), vec![10, 20, 10]);
+}
+
+#[test]
+fn spatial_index_finds_items_near_a_position() {
+    use crate::SpatialGrid;
+
+    let mut cluster = Cluster::<[f32; 3], bool>::new(0, 8, DataManager::new(1));
+    cluster.spatial = Some(SpatialGrid::new(10.0, |p| *p));
+
+    let near = cluster.spawn().unwrap();
+    let far = cluster.spawn().unwrap();
+    let moving = cluster.spawn().unwrap();
+    *cluster.fetch(&near).unwrap() = [1.0, 2.0, 0.0];
+    *cluster.fetch(&far).unwrap() = [55.0, -40.0, 0.0];
+    *cluster.fetch(&moving).unwrap() = [-12.0, 0.0, 0.0];
+    cluster.update_spatial();
+
+    assert_eq!(cluster.query_radius([0.0, 0.0, 0.0], 5.0), vec![near.clone()]);
+    assert_eq!(cluster.query_radius([0.0, 0.0, 0.0], 13.0).len(), 2);
+    assert_eq!(cluster.query_aabb([50.0, -50.0, -1.0], [60.0, 0.0, 1.0]), vec![far.clone()]);
+
+    *cluster.fetch(&moving).unwrap() = [58.0, -35.0, 0.0];
+    cluster.update_spatial();
+    assert_eq!(cluster.query_radius([0.0, 0.0, 0.0], 13.0), vec![near.clone()]);
+    assert_eq!(cluster.query_aabb([50.0, -50.0, -1.0], [60.0, 0.0, 1.0]).len(), 2);
+
+    cluster.destroy(far);
+    assert_eq!(cluster.query_aabb([50.0, -50.0, -1.0], [60.0, 0.0, 1.0]), vec![moving]);
+    assert_eq!(cluster.spatial.as_ref().unwrap().len(), 2);
+
+    // boxes far larger than the occupied cells stay cheap
+    assert_eq!(cluster.query_radius([0.0, 0.0, 0.0], f32::INFINITY).len(), 2);
+    assert_eq!(cluster.query_aabb([-1e30; 3], [1e30; 3]).len(), 2);
+
+    // destroyed through the pool, the grid only finds out on a rescan
+    cluster.pool.destroy(near);
+    assert_eq!(cluster.query_radius([0.0, 0.0, 0.0], 13.0).len(), 0);
+    cluster.update_spatial();
+    assert_eq!(cluster.spatial.as_ref().unwrap().len(), 2);
+    cluster.spatial.as_mut().unwrap().rescan();
+    cluster.update_spatial();
+    assert_eq!(cluster.spatial.as_ref().unwrap().len(), 1);
+}
+
+#[test]
+fn pool_hooks_clean_up_recycled_items() {
+    use crate::{PoolHooks, Poolable};
+
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Particle { age: u32, alive: bool }
+
+    impl Poolable for Particle {
+        fn on_spawn(&mut self) { self.alive = true; }
+        fn on_destroy(&mut self) { self.alive = false; }
+    }
+
+    let mut cluster = Cluster::<Particle, bool>::new(0, 1, DataManager::new(1));
+    cluster.set_hooks(PoolHooks::poolable());
+
+    let spawn = cluster.spawn().unwrap();
+    assert_eq!(cluster.fetch(&spawn), Some(&mut Particle { age: 0, alive: true }));
+    cluster.fetch(&spawn).unwrap().age = 12;
+
+    cluster.destroy(spawn.clone());
+    assert_eq!(cluster.pool.items[spawn.pool_index], Particle { age: 12, alive: false });
+
+    let spawn = cluster.spawn().unwrap();
+    assert_eq!(cluster.fetch(&spawn), Some(&mut Particle { age: 0, alive: true }));
+
+    let mut pool = ObjectPool::<u32>::new(0, 1);
+    let spawn = pool.spawn().unwrap();
+    *pool.fetch(&spawn).unwrap() = 5;
+    pool.destroy(spawn);
+    pool.set_hooks(PoolHooks::reset_to_default());
+    let spawn = pool.spawn().unwrap();
+    assert_eq!(pool.fetch(&spawn), Some(&mut 0));
+}
+
+#[test]
+fn items_with_a_ttl_are_destroyed_when_expired() {
+    let mut cluster = Cluster::<bool, bool>::new(0, 4, DataManager::new(1));
+    cluster.set_build_factory("bullet", |x| *x = true);
+
+    let short = cluster.spawn_with_ttl(0.05).unwrap();
+    let long = cluster.build_with_ttl("bullet", 2.0).unwrap();
+    let forever = cluster.spawn().unwrap();
+    assert_eq!(cluster.fetch(&long), Some(&mut true));
+
+    cluster.expire_items(&0.03);
+    assert_eq!(cluster.count(), 3);
+
+    cluster.expire_items(&0.03);
+    assert_eq!(cluster.fetch(&short), None);
+    assert_eq!(cluster.count(), 2);
+
+    for _ in 0..100 { cluster.expire_items(&0.02); }
+    assert_eq!(cluster.fetch(&long), None);
+    assert_eq!(cluster.fetch(&forever), Some(&mut false));
+    assert!(cluster.lifetimes.is_empty());
+}
+
+#[test]
+fn pending_ttls_keep_running_after_a_restart() {
+    let mut thread_pool = ThreadPool::<bool, bool>::new(1, 4);
+    thread_pool.start(|c| { c.spawn_with_ttl(0.3); c.spawn(); }, |_c, _dt| {});
+    assert_eq!(thread_pool.cluster_count_items(0), Some(2));
+    thread_pool.stop();
+    assert_eq!(thread_pool.snapshot().unwrap().pools[0].lifetimes.len(), 1);
+
+    thread_pool.start(|_c| {}, |_c, _dt| {});
+    thread::sleep(Duration::from_millis(600));
+    assert_eq!(thread_pool.cluster_count_items(0), Some(1));
+    thread_pool.stop();
+    assert!(thread_pool.snapshot().unwrap().pools[0].lifetimes.is_empty());
+}
+
+#[test]
+fn stopping_keeps_the_pools_of_clusters_that_did_not_panic() {
+    let mut thread_pool = ThreadPool::<bool, bool>::new(2, 4);
+    thread_pool.start(|c| { c.spawn(); }, |c, _dt| if *c.thread_id() == 1 { panic!("cluster failed") });
+    thread::sleep(Duration::from_millis(100));
+    thread_pool.stop();
+
+    let snapshot = thread_pool.snapshot().unwrap();
+    assert_eq!(snapshot.pools[0].count(), 1);
+    assert_eq!((snapshot.pools[1].count(), snapshot.pools[1].capacity()), (0, 4));
+    assert_eq!(crate::pooling::id_generation(snapshot.pools[1].spawn_id_counter), 1);
+
+    // removing a cluster that panicked migrates nothing
+    thread_pool.start(|_c| {}, |c, _dt| if *c.thread_id() == 1 { panic!("cluster failed") });
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(thread_pool.remove_cluster(1, crate::DrainPolicy::Migrate), Some(0));
+    assert_eq!(thread_pool.cluster_count_items(0), Some(1));
+    thread_pool.stop();
+}
+
+#[test]
+fn cluster_timers_fire_from_the_update_loop() {
+    let mut cluster = Cluster::<bool, (u32, u32)>::new(0, 2, DataManager::new(1));
+
+    let once = cluster.after(0.1, |c| c.shared.write(0, |d| d.0 += 1));
+    let ticker = cluster.every(0.05, |c| c.shared.write(0, |d| d.1 += 1));
+
+    for _ in 0..4 { cluster.fire_timers(&0.02); }
+    assert_eq!(cluster.shared.unlinked(0), (0, 1));
+
+    for _ in 0..7 { cluster.fire_timers(&0.02); }
+    assert_eq!(cluster.shared.unlinked(0), (1, 4));
+
+    cluster.cancel_timer(ticker);
+    cluster.cancel_timer(once);
+    for _ in 0..10 { cluster.fire_timers(&0.02); }
+    assert_eq!(cluster.shared.unlinked(0), (1, 4));
+    assert!(cluster.timers.is_empty());
+    assert!(cluster.pending_timers.is_empty());
+}
+
+#[test]
+fn published_events_reach_subscribers_of_every_cluster() {
+    struct Digit(u32);
+    struct Relay;
+
+    let mut thread_pool = ThreadPool::<bool, u32>::new(2, 2);
+    assert!(!thread_pool.publish(Relay));
+
+    thread_pool.start(
+        |c| {
+            c.subscribe(|c, digit: &Digit| {
+                let id = *c.thread_id();
+                c.shared.catch(id, &digit.0, |digit, total| *total = *total * 10 + digit);
+            });
+            // events published by a cluster reach every cluster as well
+            if *c.thread_id() == 0 { c.subscribe(|c, _: &Relay| { c.publish(Digit(9)); }); }
+        },
+        |_c, _dt| {}
+    );
+    assert!(thread_pool.publish(Digit(1)));
+    assert!(thread_pool.publish(Digit(2)));
+    assert!(thread_pool.publish(Relay));
+    thread::sleep(Duration::from_millis(20));
+    thread_pool.stop();
+
+    assert_eq!(thread_pool.shared.unlinked(0), 129);
+    assert_eq!(thread_pool.shared.unlinked(1), 129);
+    assert!(!thread_pool.publish(Relay));
+}
+
+#[test]
+fn running_clusters_can_be_controlled_from_the_main_thread() {
+    let mut thread_pool = ThreadPool::<u32, bool>::new(3, 4);
+    assert!(thread_pool.with_cluster_reply(0, |c| c.spawn()).is_none());
+
+    thread_pool.start(|_c| {}, |_c, _dt| {});
+
+    let spawn = thread_pool.with_cluster_reply(1, |c| c.spawn()).unwrap()
+        .recv().unwrap().unwrap();
+    assert!(thread_pool.with_cluster(1, move |c| *c.fetch(&spawn).unwrap() = 7));
+    assert_eq!(thread_pool.broadcast(|c| { c.spawn(); }), 3);
+
+    let mut counts: Vec<(usize, usize)> = thread_pool.broadcast_reply(|c| c.count()).iter().collect();
+    counts.sort();
+    assert_eq!(counts, vec![(0, 1), (1, 2), (2, 1)]);
+
+    let items = thread_pool.with_cluster_reply(1, |c| {
+        let mut items = Vec::new();
+        c.pool.for_each_active(|pool| items.push(*pool.target()));
+        items
+    }).unwrap().recv().unwrap();
+    assert!(items.contains(&7));
+
+    thread_pool.stop();
+    assert!(!thread_pool.with_cluster(1, |c| { c.spawn(); }));
+    assert_eq!(thread_pool.broadcast(|c| { c.spawn(); }), 0);
+}
+
+#[test]
+fn cluster_state_can_be_queried_from_the_main_thread() {
+    let mut thread_pool = ThreadPool::<u32, bool>::new(2, 4);
+    assert_eq!(thread_pool.item_counts(), vec![0, 0]);
+    assert_eq!(thread_pool.cluster_count_items(2), None);
+
+    thread_pool.start(|c| { if *c.thread_id() == 1 { c.spawn(); } }, |_c, _dt| {});
+    let spawn = thread_pool.with_cluster_reply(0, |c| {
+        let spawn = c.spawn().unwrap();
+        *c.fetch(&spawn).unwrap() = 3;
+        spawn
+    }).unwrap().recv().unwrap();
+
+    assert_eq!(thread_pool.cluster_count_items(0), Some(1));
+    assert_eq!(thread_pool.item_counts(), vec![1, 1]);
+    assert_eq!(thread_pool.read_item(0, &spawn), Some(3));
+
+    thread_pool.input(0, crate::ClusterInput::Destroy(spawn.clone()));
+    assert_eq!(thread_pool.read_item(0, &spawn), None);
+
+    thread_pool.stop();
+    assert_eq!(thread_pool.item_counts(), vec![0, 1]);
+}
+
+#[test]
+fn spawns_are_only_valid_on_their_own_cluster() {
+    let mut first = Cluster::<u32, bool>::new(0, 2, DataManager::new(2));
+    let mut second = Cluster::<u32, bool>::new(1, 2, DataManager::new(2));
+    let spawn = first.spawn().unwrap();
+    second.spawn().unwrap();
+    assert_eq!(*spawn.thread_id(), 0);
+
+    assert_eq!(second.fetch(&spawn), None);
+    second.destroy(spawn.clone());
+    assert_eq!(second.count(), 1);
+
+    let mut thread_pool = ThreadPool::<u32, bool>::new(2, 2);
+    thread_pool.start(|c| { c.spawn(); }, |_c, _dt| {});
+    let spawn = thread_pool.with_cluster_reply(1, |c| c.spawn()).unwrap().recv().unwrap().unwrap();
+
+    assert!(thread_pool.route(spawn.clone(), |c, spawn| *c.fetch(&spawn).unwrap() = 4));
+    assert_eq!(thread_pool.read_item(1, &spawn), Some(4));
+    assert_eq!(thread_pool.read_item(0, &spawn), None);
+
+    assert!(thread_pool.route(spawn.clone(), |c, spawn| c.destroy(spawn)));
+    assert_eq!(thread_pool.read_item(1, &spawn), None);
+    assert_eq!(thread_pool.item_counts(), vec![1, 1]);
+    thread_pool.stop();
+}
+
+#[test]
+fn clusters_can_be_added_and_removed_while_running() {
+    use crate::DrainPolicy;
+
+    let mut thread_pool = ThreadPool::<u32, u32>::new(2, 4);
+    assert_eq!(thread_pool.add_cluster(|_c| {}, |_c, _dt| {}), None);
+
+    thread_pool.start(
+        |c| { for _ in 0..3 { c.spawn(); } }, 
+        |c, _dt| c.shared.write(*c.thread_id(), |d| *d += 1)
+    );
+    let added = thread_pool.add_cluster(
+        |c| { c.spawn(); }, 
+        |c, _dt| c.shared.write(*c.thread_id(), |d| *d = 100)
+    );
+    assert_eq!(added, Some(2));
+    assert_eq!(thread_pool.cluster_count, 3);
+    assert_eq!(thread_pool.item_counts(), vec![3, 3, 1]);
+    assert_eq!(thread_pool.shared.unlinked(2), 100);
+
+    // cluster 1 and 2 have room for one and three more items
+    assert_eq!(thread_pool.remove_cluster(0, DrainPolicy::Migrate), Some(3));
+    assert_eq!(thread_pool.item_counts(), vec![0, 4, 3]);
+    assert!(!thread_pool.shared.contains(0));
+    assert!(!thread_pool.with_cluster(0, |_c| {}));
+
+    assert_eq!(thread_pool.remove_cluster(2, DrainPolicy::Drop), Some(0));
+    assert_eq!(thread_pool.remove_cluster(2, DrainPolicy::Drop), None);
+    assert_eq!(thread_pool.cluster_count, 2);
+    assert_eq!(thread_pool.add_cluster(|_c| {}, |_c, _dt| {}), Some(0));
+    assert_eq!(thread_pool.remove_cluster(0, DrainPolicy::Drop), Some(0));
+    thread_pool.stop();
+
+    let snapshot = thread_pool.snapshot().unwrap();
+    assert_eq!(snapshot.removed, vec![0]);
+    assert_eq!(snapshot.pools.iter().map(|p| p.count()).collect::<Vec<usize>>(), vec![0, 4]);
+
+    // removed clusters stay removed when the pool is started again
+    let mut restored = ThreadPool::from_snapshot(snapshot).unwrap();
+    restored.start(|_c| {}, |_c, _dt| {});
+    assert!(!restored.with_cluster(0, |_c| {}));
+    assert_eq!(restored.item_counts(), vec![0, 4]);
+    restored.stop();
+}
+
+#[test]
+fn running_pools_can_be_shared_between_threads() {
+    use std::sync::Arc;
+
+    let mut thread_pool = ThreadPool::<u32, u32>::new(2, 4);
+    thread_pool.start(|c| { c.spawn(); }, |c, _dt| c.shared.write(*c.thread_id(), |d| *d = 1));
+    let thread_pool = Arc::new(thread_pool);
+
+    let shared_pool = Arc::clone(&thread_pool);
+    let counts = thread::spawn(move || {
+        shared_pool.wake(0);
+        shared_pool.item_counts()
+    }).join().unwrap();
+    assert_eq!(counts, vec![1, 1]);
+
+    let mut thread_pool = Arc::into_inner(thread_pool).unwrap();
+    thread_pool.stop();
+    assert_eq!(thread_pool.shared.unlinked_all(), vec![1, 1]);
+}
+
+#[test]
+fn clusters_reusing_a_thread_id_reject_spawns_of_removed_ones() {
+    use crate::DrainPolicy;
+
+    let mut thread_pool = ThreadPool::<u32, u32>::new(3, 4);
+    thread_pool.start(|c| { c.spawn(); }, |_c, _dt| {});
+    let removed = Spawn{ thread_id: 1, id: 0, self_index: 0, pool_index: 0 };
+    assert_eq!(thread_pool.read_item(1, &removed), Some(0));
+
+    assert_eq!(thread_pool.remove_cluster(1, DrainPolicy::Drop), Some(0));
+    assert_eq!(thread_pool.add_cluster(|c| { c.spawn(); }, |_c, _dt| {}), Some(1));
+    assert_eq!(thread_pool.item_counts(), vec![1, 1, 1]);
+    assert_eq!(thread_pool.read_item(1, &removed), None);
+
+    // the generation survives stopping and restoring the pool
+    assert_eq!(thread_pool.remove_cluster(1, DrainPolicy::Drop), Some(0));
+    thread_pool.stop();
+    let mut restored = ThreadPool::from_snapshot(thread_pool.snapshot().unwrap()).unwrap();
+    restored.start(|_c| {}, |_c, _dt| {});
+    assert_eq!(restored.add_cluster(|c| { c.spawn(); }, |_c, _dt| {}), Some(1));
+    assert_eq!(restored.read_item(1, &removed), None);
+    restored.stop();
+}
+
+#[test]
+fn cluster_groups_run_their_own_handlers_and_tick_rates() {
+    use crate::ClusterGroup;
+
+    let mut thread_pool = ThreadPool::<bool, u32>::new(3, 2);
+    let ai = ClusterGroup { 
+        name: "ai", 
+        thread_ids: vec![0], 
+        setup: |_c| {}, 
+        update: |c, _dt| c.shared.write(0, |d| *d += 1),
+        tick_rate: Some(20.0),
+    };
+    let physics = ClusterGroup { 
+        name: "physics", 
+        thread_ids: vec![1], 
+        setup: |c| assert_eq!(c.group(), Some("physics")), 
+        update: |c, _dt| c.shared.write(1, |d| *d += 1),
+        tick_rate: None,
+    };
+    assert!(thread_pool.add_group(ai));
+    assert!(thread_pool.add_group(physics));
+    assert!(!thread_pool.add_group(ClusterGroup { 
+        name: "other", thread_ids: vec![2, 1], setup: |_c| {}, update: |_c, _dt| {}, tick_rate: None 
+    }));
+    assert_eq!(thread_pool.group_of(0), Some("ai"));
+    assert_eq!(thread_pool.group_of(2), None);
+
+    thread_pool.start(
+        |c| c.shared.write(*c.thread_id(), |d| *d = 1000), 
+        |_c, _dt| {}
+    );
+    thread::sleep(Duration::from_millis(200));
+    thread_pool.stop();
+
+    // wall clock ticks vary, the paced group only has to fall behind
+    assert!(thread_pool.shared.unlinked(1) > thread_pool.shared.unlinked(0));
+    assert_eq!(thread_pool.shared.unlinked(2), 1000);
+
+    // virtual time ticks the paced group exactly at its rate
+    let mut simulation = crate::Simulation::from_pool(&mut thread_pool, |_c| {}, |_c, _dt| {}).unwrap();
+    simulation.shared.write(0, |d| *d = 0);
+    let physics_ticks = simulation.shared.unlinked(1);
+    simulation.run(40, 0.01);
+    assert_eq!(simulation.shared.unlinked(0), 8);
+    assert_eq!(simulation.shared.unlinked(1), physics_ticks + 40);
+}
+
+#[test]
+fn slow_cluster_groups_answer_tasks_and_stop_without_waiting_a_tick() {
+    use crate::ClusterGroup;
+    use std::time::Instant;
+
+    let mut thread_pool = ThreadPool::<u32, u32>::new(1, 2);
+    thread_pool.add_group(ClusterGroup {
+        name: "slow",
+        thread_ids: vec![0],
+        setup: |_c| {},
+        update: |c, _dt| c.shared.write(0, |d| *d += 1),
+        tick_rate: Some(0.5),
+    });
+    thread_pool.start(|_c| {}, |_c, _dt| {});
+    while thread_pool.shared.unlinked(0) == 0 { thread::yield_now(); }
+
+    let started = Instant::now();
+    let reply = thread_pool.with_cluster_reply(0, |c| c.count()).unwrap();
+    assert_eq!(reply.recv(), Ok(0));
+    thread_pool.stop();
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn parked_clusters_wake_up_for_tasks_events_and_stop() {
+    use crate::IdleStrategy;
+    struct Wake;
+
+    let mut thread_pool = ThreadPool::<bool, u32>::new(1, 2);
+    thread_pool.set_idle_strategy(IdleStrategy::Park { spins: 2, timeout: Duration::from_secs(5) });
+    thread_pool.start(
+        |c| c.subscribe(|c, _: &Wake| c.shared.write(0, |d| *d += 100)), 
+        |c, _dt| c.shared.write(0, |d| *d += 1)
+    );
+    thread::sleep(Duration::from_millis(50));
+    assert!(thread_pool.shared.unlinked(0) <= 3);
+
+    let started = std::time::Instant::now();
+    assert_eq!(thread_pool.with_cluster_reply(0, |c| c.idle_strategy()).unwrap().recv().ok(), 
+        Some(IdleStrategy::Park { spins: 2, timeout: Duration::from_secs(5) }));
+
+    thread::sleep(Duration::from_millis(20));
+    assert!(thread_pool.publish(Wake));
+    for _ in 0..100 {
+        if thread_pool.shared.unlinked(0) > 100 { break; }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(thread_pool.shared.unlinked(0) > 100);
+
+    thread_pool.stop();
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn reactive_clusters_only_tick_when_woken_up() {
+    use crate::IdleStrategy;
+
+    fn wait_for(thread_pool: &ThreadPool<bool, (u32, u32)>, thread_id: usize, ticks: u32) -> u32 {
+        for _ in 0..100 {
+            if thread_pool.shared.unlinked(thread_id).0 >= ticks { break; }
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread_pool.shared.unlinked(thread_id).0
+    }
+
+    let mut thread_pool = ThreadPool::<bool, (u32, u32)>::new(2, 2);
+    thread_pool.set_idle_strategy(IdleStrategy::Reactive);
+    thread_pool.start(
+        |c| match *c.thread_id() {
+            0 => { c.after(0.3, |_c| {}); },
+            _ => c.watch_shared(0),
+        }, 
+        |c, _dt| c.shared.write(*c.thread_id(), |d| d.0 += 1)
+    );
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(thread_pool.shared.unlinked_all(), vec![(0, 0), (0, 0)]);
+
+    assert!(thread_pool.wake(1));
+    assert_eq!(wait_for(&thread_pool, 1, 1), 1);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(thread_pool.shared.unlinked(1).0, 1);
+
+    // writes to a watched cell wake the watching cluster
+    thread_pool.shared.write(0, |d| d.1 = 5);
+    assert_eq!(wait_for(&thread_pool, 1, 2), 2);
+
+    // the timer wakes the first cluster, whose tick writes its cell
+    assert!(wait_for(&thread_pool, 0, 1) >= 1);
+    assert!(wait_for(&thread_pool, 1, 3) >= 3);
+    assert!(!thread_pool.wake(2));
+
+    thread_pool.stop();
+}
+
+#[test]
+fn pools_spawn_and_destroy_in_bulk() {
+    let mut pool = ObjectPool::<u32>::new(0, 6);
+    let spawns = pool.spawn_many_with(4, |i, item| *item = i as u32 * 10);
+    assert_eq!(spawns.len(), 4);
+    assert_eq!(pool.get(&spawns[3]), Some(&30));
+    assert_eq!(pool.spawn_many(4).len(), 2);
+
+    assert_eq!(pool.destroy_many(&spawns[0..2]), 2);
+    assert_eq!(pool.destroy_many(&spawns[0..2]), 0);
+    assert_eq!(pool.get(&spawns[2]), Some(&20));
+    assert_eq!(pool.destroy_where(|item| *item == 0), 2);
+    assert_eq!(pool.count(), 2);
+    assert_eq!(pool.get(&spawns[3]), Some(&30));
+
+    pool.clear();
+    assert_eq!(pool.count(), 0);
+    assert_eq!(pool.get(&spawns[3]), None);
+    let spawn = pool.spawn().unwrap();
+    assert_eq!((spawn.pool_index, spawn.id), (0, 6));
+
+    let mut cluster = Cluster::<[f32; 3], bool>::new(0, 8, DataManager::new(1));
+    cluster.spatial = Some(crate::SpatialGrid::new(1.0, |p| *p));
+    cluster.spawn_many_with(8, |i, p| p[0] = i as f32);
+    cluster.update_spatial();
+    assert_eq!(cluster.destroy_where(|p| p[0] >= 4.0), 4);
+    assert_eq!(cluster.query_radius([6.0, 0.0, 0.0], 3.0).len(), 1);
+    cluster.clear();
+    assert_eq!(cluster.query_radius([0.0, 0.0, 0.0], 10.0).len(), 0);
+}
+
+#[test]
+fn clusters_retain_items_while_updating_them() {
+    let mut cluster = Cluster::<u32, bool>::new(0, 6, DataManager::new(1));
+    let spawns = cluster.spawn_many_with(6, |i, health| *health = i as u32);
+
+    let mut visited = Vec::new();
+    let destroyed = cluster.retain_mut(|health, spawn| {
+        visited.push(spawn.clone());
+        *health = health.saturating_sub(2);
+        *health > 0
+    });
+    assert_eq!(destroyed, 3);
+    assert_eq!(visited.len(), 6);
+    assert!(spawns.iter().all(|spawn| visited.contains(spawn)));
+
+    assert_eq!(cluster.count(), 3);
+    assert_eq!(cluster.fetch(&spawns[1]), None);
+    assert_eq!(cluster.fetch(&spawns[5]), Some(&mut 3));
+}
+
+#[cfg(feature = "soa")]
+#[test]
+fn soa_pools_keep_spawns_valid_and_yield_field_slices() {
+    use crate::{SoA, SoaPool};
+
+    #[derive(SoA, Default, Clone, Debug, PartialEq)]
+    struct Particle { position: f32, velocity: f32 }
+
+    let mut pool = SoaPool::<Particle>::new(0, 4);
+    let spawns: Vec<Spawn> = (0..4).map(|_| pool.spawn().unwrap()).collect();
+    for (i, spawn) in spawns.iter().enumerate() {
+        pool.set(spawn, Particle { position: 0.0, velocity: i as f32 });
+    }
+    assert!(pool.spawn().is_none());
+
+    pool.destroy(spawns[1].clone());
+    assert_eq!(pool.count(), 3);
+    assert_eq!(pool.get(&spawns[1]), None);
+    assert_eq!(pool.index_of(&spawns[3]), Some(1));
+
+    let slices = pool.slices_mut();
+    for (position, velocity) in slices.position.iter_mut().zip(slices.velocity.iter()) {
+        *position += velocity * 2.0;
+    }
+    assert_eq!(pool.slices().position, &[0.0, 6.0, 4.0]);
+    assert_eq!(pool.get(&spawns[2]), Some(Particle { position: 4.0, velocity: 2.0 }));
+    assert_eq!(pool.spawn_at(1), Some(&spawns[3]));
+
+    // spawns of standalone pools never validate in a cluster
+    let mut cluster = Cluster::<bool, bool>::new(0, 4, DataManager::new(1));
+    let item = cluster.spawn().unwrap();
+    assert_eq!(cluster.fetch(&spawns[0]), None);
+    assert_eq!(pool.get(&item), None);
+}
+
+#[test]
+fn dense_pools_keep_active_items_packed() {
+    let mut pool = ObjectPool::<u32>::new(0, 6);
+    let spawns = pool.spawn_many_with(6, |i, item| *item = i as u32);
+    pool.set_dense(true);
+
+    pool.destroy(spawns[1].clone());
+    pool.destroy_where(|item| *item == 3);
+    assert_eq!(pool.active_items(), Some(&[0, 5, 2, 4][..]));
+    assert_eq!(pool.get(&spawns[5]), Some(&5));
+    assert_eq!(pool.get(&spawns[1]), None);
+
+    let spawn = pool.spawn().unwrap();
+    *pool.fetch(&spawn).unwrap() = 9;
+    pool.sort_by_key(|item| *item);
+    assert_eq!(pool.active_items(), Some(&[0, 2, 4, 5, 9][..]));
+    pool.active_items_mut().unwrap().iter_mut().for_each(|item| *item += 1);
+
+    pool.set_dense(false);
+    assert_eq!(pool.active_items(), None);
+    assert_eq!(pool.get(&spawns[4]), Some(&5));
+    assert_eq!(pool.get(&spawn), Some(&10));
+
+    // items are swapped into place, whatever the order was before
+    let mut pool = ObjectPool::<u32>::new(0, 16);
+    let spawns = pool.spawn_many_with(16, |i, item| *item = (i as u32 * 7) % 16);
+    pool.destroy_where(|item| item.is_multiple_of(3));
+    for dense in [true, false, true] {
+        pool.set_dense(dense);
+        pool.sort_by_key(|item| u32::MAX - *item);
+        pool.sort_by_spawn_order();
+        pool.sort_by_key(|item| *item % 5);
+        assert!(pool.is_consistent());
+        for (i, spawn) in spawns.iter().enumerate() {
+            let value = (i as u32 * 7) % 16;
+            assert_eq!(pool.get(spawn), (!value.is_multiple_of(3)).then_some(&value));
+        }
+    }
+    let keys: Vec<u32> = pool.active_items().unwrap().iter().map(|item| item % 5).collect();
+    assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn simulations_run_clusters_on_the_calling_thread_in_virtual_time() {
+    use crate::{ClusterGroup, Simulation};
+
+    let mut pool = ThreadPool::<u32, f32>::new(3, 4);
+    pool.add_group(ClusterGroup {
+        name: "slow",
+        thread_ids: vec![2],
+        setup: |_| {},
+        update: |cluster, delta_time| cluster.shared.catch(2, delta_time, |dt, time| *time += dt),
+        tick_rate: Some(10.0),
+    });
+    let mut simulation = Simulation::from_pool(&mut pool, 
+        |cluster| { cluster.spawn(); },
+        |cluster, delta_time| {
+            let thread_id = *cluster.pool.thread_id();
+            cluster.shared.catch(thread_id, delta_time, |dt, time| *time += dt);
+        },
+    ).unwrap();
+
+    simulation.run(10, 0.025);
+    assert_eq!(simulation.ticks(), 10);
+    assert!((simulation.time() - 0.25).abs() < 1e-6);
+    let shared = simulation.shared.unlinked_all();
+    assert!((shared[0] - 0.25).abs() < 1e-6 && (shared[1] - 0.25).abs() < 1e-6);
+    // ticked at 0.1 and 0.2
+    assert!((shared[2] - 0.2).abs() < 1e-6);
+
+    simulation.with_cluster(1, |cluster| { cluster.spawn(); });
+    assert_eq!(simulation.cluster(1).unwrap().count(), 1);
+    simulation.step(0.025);
+    assert_eq!(simulation.cluster(1).unwrap().count(), 2);
+    assert_eq!(simulation.snapshot().pools[1].count(), 2);
+
+    // systems of one stage would otherwise run on worker threads
+    thread_local! { static SIMULATING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) }; }
+    SIMULATING.with(|simulating| simulating.set(true));
+    for name in ["first", "second"] {
+        let system = crate::System { name, reads: vec![], writes: vec![], run: |_ctx, _dt| assert!(SIMULATING.with(|s| s.get())) };
+        simulation.cluster(1).unwrap().schedule(system).unwrap();
+    }
+    assert_eq!(simulation.cluster(1).unwrap().schedule_stages(), 1);
+    simulation.step(0.025);
+
+    // a cluster removing itself leaves a pool of the next generation behind
+    simulation.with_cluster(0, |cluster| cluster.removed = true);
+    simulation.step(0.025);
+    assert!(simulation.cluster(0).is_none());
+    let snapshot = simulation.snapshot();
+    assert_eq!(snapshot.removed, vec![0]);
+    assert_eq!(crate::pooling::id_generation(snapshot.pools[0].spawn_id_counter), 1);
+}
@@ -0,0 +1,113 @@
+/// Hashed timer wheel driven by delta time. Entries are bucketed by the tick
+/// they are due on, so advancing only touches the buckets of elapsed ticks
+/// instead of every scheduled entry.
+pub struct TimerWheel<T> {
+    resolution: f32,
+    slots: Vec<Vec<(u64, T)>>,
+    current_tick: u64,
+    accumulated: f32,
+    expired: Vec<(u64, T)>,
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    /// Creates a wheel that advances in steps of `resolution` seconds, with
+    /// `slot_count` buckets per revolution.
+    pub fn new(resolution: f32, slot_count: usize) -> Self {
+        TimerWheel {
+            resolution,
+            slots: (0..slot_count.max(1)).map(|_| Vec::new()).collect(),
+            current_tick: 0,
+            accumulated: 0.0,
+            expired: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Seconds until the next wheel step, useful to know how long nothing can expire.
+    pub fn until_next_step(&self) -> f32 { self.resolution - self.accumulated }
+
+    /// Seconds until the earliest scheduled entry expires. This looks at 
+    /// every entry, so it is meant for deciding how long to sleep.
+    pub fn until_next_due(&self) -> Option<f32> {
+        let next_due = self.slots.iter().flatten().map(|(due_tick, _)| *due_tick).min()?;
+        let steps = next_due.saturating_sub(self.current_tick + 1);
+        Some(steps as f32 * self.resolution + self.until_next_step())
+    }
+
+    /// Schedules an entry to expire after `delay` seconds, rounded up to the
+    /// wheel's resolution and always at least one step from now.
+    pub fn schedule(&mut self, delay: f32, entry: T) {
+        let steps = ((delay + self.accumulated) / self.resolution).ceil().max(1.0) as u64;
+        let due_tick = self.current_tick + steps;
+        self.insert(due_tick, entry);
+    }
+
+    /// Schedules an entry `delay` seconds after the step it was due on when
+    /// it last expired, so repeating entries do not drift. Entries that would
+    /// be due already expire on the next step.
+    pub fn reschedule(&mut self, last_due_tick: u64, delay: f32, entry: T) {
+        let steps = (delay / self.resolution).ceil().max(1.0) as u64;
+        let due_tick = (last_due_tick + steps).max(self.current_tick + 1);
+        self.insert(due_tick, entry);
+    }
+
+    fn insert(&mut self, due_tick: u64, entry: T) {
+        let slot = (due_tick % self.slots.len() as u64) as usize;
+
+        self.slots[slot].push((due_tick, entry));
+        self.len += 1;
+    }
+
+    /// Every entry that has not been taken yet, with the seconds until it 
+    /// expires, which are 0 for entries that expired already.
+    pub fn pending(&self) -> impl Iterator<Item = (f32, &T)> {
+        let expired = self.expired.iter().map(|(_, entry)| (0.0, entry));
+        let scheduled = self.slots.iter().flatten().map(|(due_tick, entry)| {
+            let steps = due_tick.saturating_sub(self.current_tick + 1);
+            (steps as f32 * self.resolution + self.until_next_step(), entry)
+        });
+        expired.chain(scheduled)
+    }
+
+    /// Moves the wheel forward by delta time, collecting everything that expired.
+    pub fn advance(&mut self, delta_time: f32) {
+        self.accumulated += delta_time;
+
+        while self.accumulated >= self.resolution {
+            self.accumulated -= self.resolution;
+            self.current_tick += 1;
+
+            let slot_index = (self.current_tick % self.slots.len() as u64) as usize;
+            let slot = &mut self.slots[slot_index];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].0 <= self.current_tick {
+                    self.expired.push(slot.swap_remove(i));
+                    self.len -= 1;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Takes the next entry that expired during previous calls to `advance`.
+    pub fn pop_expired(&mut self) -> Option<T> {
+        self.expired.pop().map(|(_, entry)| entry)
+    }
+
+    /// Same as `pop_expired`, together with the step the entry was due on.
+    pub fn pop_expired_with_due(&mut self) -> Option<(u64, T)> {
+        self.expired.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| slot.clear());
+        self.expired.clear();
+        self.len = 0;
+    }
+}
@@ -0,0 +1,27 @@
+use std::{sync::{Arc, OnceLock, atomic::{AtomicBool, Ordering}}, thread::{self, Thread}};
+
+/// Wakes a cluster up that parks while idle. The flag is set before the
+/// thread is unparked, so wake-ups sent before the cluster parks are not lost.
+#[derive(Clone, Default)]
+pub(crate) struct WakeSignal(Arc<(AtomicBool, OnceLock<Thread>)>);
+
+impl WakeSignal {
+    // called from the cluster's own thread before it first parks
+    pub(crate) fn attach(&self) {
+        let _ = self.0.1.set(thread::current());
+    }
+
+    pub(crate) fn wake(&self) {
+        self.0.0.store(true, Ordering::Release);
+        if let Some(thread) = self.0.1.get() { thread.unpark(); }
+    }
+
+    /// Whether the cluster was woken up since the last call.
+    pub(crate) fn take(&self) -> bool {
+        self.0.0.swap(false, Ordering::AcqRel)
+    }
+
+    pub(crate) fn is_current_thread(&self) -> bool {
+        self.0.1.get().is_some_and(|thread| thread.id() == thread::current().id())
+    }
+}